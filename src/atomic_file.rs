@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Numbers the temporary files of this process, so each save gets a fresh one
+static TMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Replaces the file at `path` with the content produced by `write`, crash-safely.
+///
+/// The content is written to a temporary file next to the target, flushed to disk and
+/// then renamed over the original, so readers only ever see the old or the new file.
+/// The permissions of an existing target are preserved.
+pub fn write_atomically<P, F>(path: P, write: F) -> Result<(), Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> Result<(), Box<dyn Error>>,
+{
+    // Follow symlinks so the link itself is not replaced by a regular file
+    let path = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Not a file path: {}", path.display()))?;
+    // A file left behind by a crashed program with the same process id is skipped
+    let (tmp_path, mut file) = loop {
+        let tmp_path = dir.join(format!(
+            ".{}.{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(file) => break (tmp_path, file),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Cannot save to directory {}: {}", dir.display(), e).into()),
+        }
+    };
+
+    if let Err(e) = fill_and_sync(&mut file, &path, write) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    drop(file);
+
+    if let Err(e) = fs::rename(&tmp_path, &path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Cannot replace {}: {}", path.display(), e).into());
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+fn fill_and_sync<F>(file: &mut File, target: &Path, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut File) -> Result<(), Box<dyn Error>>,
+{
+    write(file)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn stale_temporary_files_do_not_block_saving() {
+        let dir = std::env::temp_dir().join(format!("atomic-file-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("people.csv");
+        fs::write(&path, "old").unwrap();
+        // As if an earlier program with this process id had crashed while saving
+        let next = TMP_COUNTER.load(Ordering::Relaxed);
+        for n in next..next + 3 {
+            fs::write(dir.join(format!(".people.csv.{}.{}.tmp", std::process::id(), n)), "stale").unwrap();
+        }
+
+        write_atomically(&path, |file| Ok(file.write_all(b"new")?)).unwrap();
+        write_atomically(&path, |file| Ok(file.write_all(b"newer")?)).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "newer");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::{Datelike, NaiveDate};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A date of birth that may be only partly known.
+///
+/// Written as `YYYY-MM-DD`, `YYYY` when only the year is known, `--MM-DD` when the year is
+/// not known (the vCard convention), or an empty string when it is not known at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BirthDate {
+    #[default]
+    Unknown,
+    Year(i32),
+    MonthDay { month: u32, day: u32 },
+    Full(NaiveDate),
+}
+
+impl BirthDate {
+    /// Parses any of the forms `BirthDate` is written in. Returns `None` if `s` is none of them.
+    pub fn parse(s: &str) -> Option<BirthDate> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Some(BirthDate::Unknown);
+        }
+        if let Some(month_day) = s.strip_prefix("--") {
+            let (month, day) = month_day
+                .split_once('-')
+                .or_else(|| (month_day.len() == 4).then(|| month_day.split_at(2)))?;
+            return BirthDate::month_day(month.parse().ok()?, day.parse().ok()?);
+        }
+        if s.len() == 4 && s.chars().all(|c| c.is_ascii_digit()) {
+            return s.parse().ok().map(BirthDate::Year);
+        }
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(BirthDate::Full)
+    }
+
+    /// A birthday without a year. 29 February is allowed.
+    pub fn month_day(month: u32, day: u32) -> Option<BirthDate> {
+        // 2000 is a leap year, so every possible birthday exists in it
+        NaiveDate::from_ymd_opt(2000, month, day)?;
+        Some(BirthDate::MonthDay { month, day })
+    }
+
+    /// The full date, if it is known.
+    pub fn full(&self) -> Option<NaiveDate> {
+        match self {
+            BirthDate::Full(date) => Some(*date),
+            _ => None,
+        }
+    }
+
+    /// Whether both dates could be the same day: `1990` agrees with `1990-05-06` and with
+    /// `--05-06`, and an unknown date agrees with any date.
+    pub fn agrees_with(&self, other: &BirthDate) -> bool {
+        let year = |date: &BirthDate| match date {
+            BirthDate::Full(date) => Some(date.year()),
+            BirthDate::Year(year) => Some(*year),
+            _ => None,
+        };
+        let birthday = |date: &BirthDate| match date {
+            BirthDate::Full(date) => Some((date.month(), date.day())),
+            BirthDate::MonthDay { month, day } => Some((*month, *day)),
+            _ => None,
+        };
+        fn agree<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            a.is_none() || b.is_none() || a == b
+        }
+        agree(year(self), year(other)) && agree(birthday(self), birthday(other))
+    }
+
+    // Dates with a year come first in year order, then birthdays without a year, then unknown
+    fn sort_key(&self) -> (u8, i32, u32, u32) {
+        match self {
+            BirthDate::Full(date) => (0, date.year(), date.month(), date.day()),
+            BirthDate::Year(year) => (0, *year, 0, 0),
+            BirthDate::MonthDay { month, day } => (1, 0, *month, *day),
+            BirthDate::Unknown => (2, 0, 0, 0),
+        }
+    }
+}
+
+impl From<NaiveDate> for BirthDate {
+    fn from(date: NaiveDate) -> Self {
+        BirthDate::Full(date)
+    }
+}
+
+impl Ord for BirthDate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for BirthDate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BirthDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BirthDate::Unknown => Ok(()),
+            BirthDate::Year(year) => write!(f, "{:04}", year),
+            BirthDate::MonthDay { month, day } => write!(f, "--{:02}-{:02}", month, day),
+            BirthDate::Full(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full(year: i32, month: u32, day: u32) -> BirthDate {
+        BirthDate::Full(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    #[test]
+    fn parses_every_form() {
+        assert_eq!(BirthDate::parse(""), Some(BirthDate::Unknown));
+        assert_eq!(BirthDate::parse("  "), Some(BirthDate::Unknown));
+        assert_eq!(BirthDate::parse("1990"), Some(BirthDate::Year(1990)));
+        assert_eq!(BirthDate::parse("--05-06"), Some(BirthDate::MonthDay { month: 5, day: 6 }));
+        assert_eq!(BirthDate::parse("--0506"), Some(BirthDate::MonthDay { month: 5, day: 6 }));
+        assert_eq!(BirthDate::parse("--02-29"), Some(BirthDate::MonthDay { month: 2, day: 29 }));
+        assert_eq!(BirthDate::parse(" 1990-05-06 "), Some(full(1990, 5, 6)));
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        assert_eq!(BirthDate::parse("--02-30"), None);
+        assert_eq!(BirthDate::parse("--13-01"), None);
+        assert_eq!(BirthDate::parse("1990-02-30"), None);
+        assert_eq!(BirthDate::parse("199"), None);
+        assert_eq!(BirthDate::parse("05/06/1990"), None);
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        for date in [
+            BirthDate::Unknown,
+            BirthDate::Year(1990),
+            BirthDate::Year(800),
+            BirthDate::MonthDay { month: 2, day: 29 },
+            full(1990, 5, 6),
+        ] {
+            assert_eq!(BirthDate::parse(&date.to_string()), Some(date), "{}", date);
+        }
+        assert_eq!(BirthDate::Year(800).to_string(), "0800");
+        assert_eq!(BirthDate::MonthDay { month: 5, day: 6 }.to_string(), "--05-06");
+    }
+
+    #[test]
+    fn partial_dates_agree_with_the_dates_they_could_be() {
+        let may_6 = BirthDate::MonthDay { month: 5, day: 6 };
+        assert!(BirthDate::Year(1990).agrees_with(&full(1990, 5, 6)));
+        assert!(may_6.agrees_with(&full(1990, 5, 6)));
+        assert!(BirthDate::Year(1990).agrees_with(&may_6));
+        assert!(BirthDate::Unknown.agrees_with(&full(1990, 5, 6)));
+        assert!(!BirthDate::Year(1991).agrees_with(&full(1990, 5, 6)));
+        assert!(!may_6.agrees_with(&full(1990, 5, 7)));
+        assert!(!full(1990, 5, 6).agrees_with(&full(1990, 5, 7)));
+    }
+
+    #[test]
+    fn dates_with_a_year_sort_first_and_unknown_last() {
+        let mut dates = vec![
+            BirthDate::Unknown,
+            BirthDate::MonthDay { month: 1, day: 2 },
+            full(1990, 5, 6),
+            BirthDate::Year(1990),
+            BirthDate::MonthDay { month: 1, day: 1 },
+            full(1989, 12, 31),
+        ];
+        dates.sort();
+        assert_eq!(
+            dates,
+            [
+                full(1989, 12, 31),
+                BirthDate::Year(1990),
+                full(1990, 5, 6),
+                BirthDate::MonthDay { month: 1, day: 1 },
+                BirthDate::MonthDay { month: 1, day: 2 },
+                BirthDate::Unknown,
+            ]
+        );
+    }
+}
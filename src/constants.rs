@@ -1,20 +1,25 @@
 use serde::de::{self, Visitor};
 use serde::{Deserializer, Serializer};
 use std::fmt;
-use gettextrs::gettext;
+use crate::sport_catalog::{catalog, SportEntry};
 
 // TRANSLATABLE STRINGS (for .po files):
 // File, People, New, Open, Exit, Add, Edit, Delete
 // ID, First Name, Last Name, Age, Favorite Sport
 // No people loaded
-// Baseball, Soccer, Basketball, Tennis, Golf, Hockey, Cricket, Rugby, Handball, Football, Volleyball, Water polo, Equestrian, Swimming, Running, Cycling, Skating, Skateboarding, Surfing, Skiing, Snowboarding, Rowing, Wrestling
+// Sport names are translated in data/sports.json, falling back to these catalogs
 // (and any other user-facing string)
 
 pub const APP_NAME: &str = "People DB";
 pub const APP_ID: &str = "com.github.arickp.rustpeopledb";
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
-pub const CSV_HEADERS: &[&str] = &["first_name", "last_name", "date_of_birth", "favorite_sport"];
-pub const GUI_TABLE_HEADER_COLUMNS: &[&str] = &["ID", "First Name", "Last Name", "Age", "Favorite Sport"];
+pub const CSV_HEADERS: &[&str] = &[
+    "id", "first_name", "last_name", "date_of_birth", "favorite_sports", "emails", "phones", "address", "notes",
+    "tags",
+];
+// Columns of `CSV_HEADERS` holding lists, with the items separated by `;`
+pub const CSV_LIST_HEADERS: &[&str] = &["favorite_sports", "emails", "phones", "tags"];
+pub const GUI_TABLE_HEADER_COLUMNS: &[&str] = &["ID", "First Name", "Last Name", "Age", "Favorite Sport", "Tags"];
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Sport {
@@ -45,65 +50,49 @@ pub enum Sport {
 }
 
 impl Sport {
-    pub fn emoji(&self) -> &'static str {
+    /// Key of the sport in the catalog, e.g. `water_polo`. Custom sports that are not in
+    /// the catalog have none.
+    pub fn key(&self) -> Option<&'static str> {
         match self {
-            Sport::Baseball => "⚾",
-            Sport::Soccer => "⚽",
-            Sport::Basketball => "🏀",
-            Sport::Tennis => "🎾",
-            Sport::Golf => "⛳",
-            Sport::Hockey => "🏒",
-            Sport::Cricket => "🏏",
-            Sport::Rugby => "🏉",
-            Sport::Handball => "🤾",
-            Sport::Football => "🏈",
-            Sport::Volleyball => "🏐",
-            Sport::WaterPolo => "🤽",
-            Sport::Equestrian => "🐎",
-            Sport::Swimming => "🏊",
-            Sport::Running => "🏃",
-            Sport::Cycling => "🚴",
-            Sport::Skating => "🛼",
-            Sport::Skateboarding => "🛹",
-            Sport::Surfing => "🏄",
-            Sport::Skiing => "🎿",
-            Sport::Snowboarding => "🏂",
-            Sport::Rowing => "🚣",
-            Sport::Wrestling => "🤼",
-            Sport::Other(_) => "",
+            Sport::Baseball => Some("baseball"),
+            Sport::Soccer => Some("soccer"),
+            Sport::Basketball => Some("basketball"),
+            Sport::Tennis => Some("tennis"),
+            Sport::Golf => Some("golf"),
+            Sport::Hockey => Some("hockey"),
+            Sport::Cricket => Some("cricket"),
+            Sport::Rugby => Some("rugby"),
+            Sport::Handball => Some("handball"),
+            Sport::Football => Some("football"),
+            Sport::Volleyball => Some("volleyball"),
+            Sport::WaterPolo => Some("water_polo"),
+            Sport::Equestrian => Some("equestrian"),
+            Sport::Swimming => Some("swimming"),
+            Sport::Running => Some("running"),
+            Sport::Cycling => Some("cycling"),
+            Sport::Skating => Some("skating"),
+            Sport::Skateboarding => Some("skateboarding"),
+            Sport::Surfing => Some("surfing"),
+            Sport::Skiing => Some("skiing"),
+            Sport::Snowboarding => Some("snowboarding"),
+            Sport::Rowing => Some("rowing"),
+            Sport::Wrestling => Some("wrestling"),
+            Sport::Other(name) => catalog().find(name).map(|e| e.key.as_str()),
         }
     }
 
-    pub fn all_known_sports() -> Vec<Sport> {
-        vec![
-            Sport::Baseball,
-            Sport::Soccer,
-            Sport::Basketball,
-            Sport::Tennis,
-            Sport::Golf,
-            Sport::Hockey,
-            Sport::Cricket,
-            Sport::Rugby,
-            Sport::Handball,
-            Sport::Football,
-            Sport::Volleyball,
-            Sport::WaterPolo,
-            Sport::Equestrian,
-            Sport::Swimming,
-            Sport::Running,
-            Sport::Cycling,
-            Sport::Skating,
-            Sport::Skateboarding,
-            Sport::Surfing,
-            Sport::Skiing,
-            Sport::Snowboarding,
-            Sport::Rowing,
-            Sport::Wrestling,
-        ]
+    /// How the sport is stored, whatever the language of the user interface: the catalog key,
+    /// or the name of a custom sport that is not in the catalog.
+    pub fn canonical(&self) -> String {
+        match self {
+            Sport::Other(name) => self.key().map_or_else(|| name.clone(), str::to_string),
+            _ => self.key().expect("built-in sports have a catalog key").to_string(),
+        }
     }
 
-    pub fn from_string(s: &str) -> Sport {
-        match s.trim().to_lowercase().replace(' ', "").as_str() {
+    /// The sport for a catalog entry: a variant for the built-in keys, otherwise `Other`.
+    pub fn from_entry(entry: &SportEntry) -> Sport {
+        match entry.key.as_str() {
             "baseball" => Sport::Baseball,
             "soccer" => Sport::Soccer,
             "basketball" => Sport::Basketball,
@@ -115,7 +104,7 @@ impl Sport {
             "handball" => Sport::Handball,
             "football" => Sport::Football,
             "volleyball" => Sport::Volleyball,
-            "waterpolo" => Sport::WaterPolo, // Accepts 'water polo' and 'waterpolo'
+            "water_polo" => Sport::WaterPolo,
             "equestrian" => Sport::Equestrian,
             "swimming" => Sport::Swimming,
             "running" => Sport::Running,
@@ -127,48 +116,55 @@ impl Sport {
             "snowboarding" => Sport::Snowboarding,
             "rowing" => Sport::Rowing,
             "wrestling" => Sport::Wrestling,
-            other => {
-                // Special normalization for water polo
-                let t = s.trim().to_lowercase();
-                if t == "water polo" || t == "waterpolo" {
-                    Sport::WaterPolo
-                } else {
-                    Sport::Other(s.trim().to_string())
-                }
-            },
+            _ => Sport::Other(entry.name.clone()),
+        }
+    }
+
+    /// The catalog entry of the sport, if it has one.
+    pub fn catalog_entry(&self) -> Option<&'static SportEntry> {
+        catalog().get(self.key()?)
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        self.catalog_entry().map_or("", |e| e.emoji.as_str())
+    }
+
+    /// Every sport in the catalog, in catalog order.
+    pub fn all_known_sports() -> Vec<Sport> {
+        catalog().sports.iter().map(Sport::from_entry).collect()
+    }
+
+    /// Looks the sport up in the catalog by key, name or alias. Anything else is a custom sport.
+    pub fn from_string(s: &str) -> Sport {
+        match catalog().find(s) {
+            Some(entry) => Sport::from_entry(entry),
+            None => Sport::Other(s.trim().to_string()),
         }
     }
+
+    /// Sports as a comma-separated list for editing as text. They are written by canonical
+    /// name, since display names can name another sport: `Football` is soccer in `en_GB`.
+    pub fn to_list_text(sports: &[Sport]) -> String {
+        sports.iter().map(Sport::canonical).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Reads a comma-separated list of sports, as written by `to_list_text`, in order.
+    pub fn from_list_text(text: &str) -> Vec<Sport> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Sport::from_string)
+            .collect()
+    }
 }
 
 impl fmt::Display for Sport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Sport::Baseball => gettext("Baseball"),
-            Sport::Soccer => gettext("Soccer"),
-            Sport::Basketball => gettext("Basketball"),
-            Sport::Tennis => gettext("Tennis"),
-            Sport::Golf => gettext("Golf"),
-            Sport::Hockey => gettext("Hockey"),
-            Sport::Cricket => gettext("Cricket"),
-            Sport::Rugby => gettext("Rugby"),
-            Sport::Handball => gettext("Handball"),
-            Sport::Football => gettext("Football"),
-            Sport::Volleyball => gettext("Volleyball"),
-            Sport::WaterPolo => gettext("Water polo"),
-            Sport::Equestrian => gettext("Equestrian"),
-            Sport::Swimming => gettext("Swimming"),
-            Sport::Running => gettext("Running"),
-            Sport::Cycling => gettext("Cycling"),
-            Sport::Skating => gettext("Skating"),
-            Sport::Skateboarding => gettext("Skateboarding"),
-            Sport::Surfing => gettext("Surfing"),
-            Sport::Skiing => gettext("Skiing"),
-            Sport::Snowboarding => gettext("Snowboarding"),
-            Sport::Rowing => gettext("Rowing"),
-            Sport::Wrestling => gettext("Wrestling"),
-            Sport::Other(s) => s.to_string(),
-        };
-        write!(f, "{}", s)
+        match (self.catalog_entry(), self) {
+            (Some(entry), _) => write!(f, "{}", entry.localized_name()),
+            (None, Sport::Other(s)) => write!(f, "{}", s),
+            (None, _) => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -177,7 +173,7 @@ impl serde::Serialize for Sport {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        serializer.serialize_str(&self.canonical())
     }
 }
 
@@ -206,3 +202,85 @@ impl<'de> serde::Deserialize<'de> for Sport {
         deserializer.deserialize_str(SportVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::person::Person;
+    use crate::sport_catalog::prefer_language;
+    use std::fs;
+    use std::sync::Mutex;
+
+    // Held by tests that read translated names, which mean other sports while a language is
+    // preferred
+    static PREFERENCE: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn sport_lists_read_back_as_the_same_sports() {
+        let _preference = PREFERENCE.lock().unwrap();
+        let sports = vec![Sport::Soccer, Sport::Football, Sport::WaterPolo, Sport::Other("Kabaddi".to_string())];
+        assert_eq!(Sport::to_list_text(&sports), "soccer, football, water_polo, Kabaddi");
+        assert_eq!(Sport::from_list_text(&Sport::to_list_text(&sports)), sports);
+        assert_eq!(Sport::from_list_text(" , soccer,,"), [Sport::Soccer]);
+
+        // The British name of soccer is the name of American football
+        let soccer = Sport::Soccer.catalog_entry().unwrap();
+        assert_eq!(soccer.translation("en_GB"), Some("Football"));
+        assert_eq!(Sport::from_list_text("Football"), [Sport::Football]);
+    }
+
+    #[test]
+    fn sports_are_stored_by_key_in_every_language() {
+        let _preference = PREFERENCE.lock().unwrap();
+        for language in [None, Some("en_GB"), Some("es")] {
+            prefer_language(language);
+            for sport in Sport::all_known_sports() {
+                let key = sport.canonical();
+                assert_eq!(Some(key.as_str()), sport.key());
+                assert_eq!(Sport::from_string(&key), sport, "{} in {:?}", key, language);
+                assert_eq!(serde_json::to_string(&sport).unwrap(), format!("\"{}\"", key));
+            }
+        }
+        prefer_language(None);
+        assert_eq!(Sport::Other("Kabaddi".to_string()).canonical(), "Kabaddi");
+    }
+
+    #[test]
+    fn translated_names_are_repaired_to_keys() {
+        let _preference = PREFERENCE.lock().unwrap();
+        let dir = std::env::temp_dir().join(format!("sport-repair-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("people.csv");
+        // Saved in British English by a version that wrote display names
+        fs::write(
+            &path,
+            format!(
+                "{}\n{}\n0,Ann,Miller,,Football; American Football,,,,,\n1,Bob,Smith,,Fútbol; Kabaddi,,,,,\n",
+                crate::migrate::metadata_line(),
+                CSV_HEADERS.join(",")
+            ),
+        )
+        .unwrap();
+        let sports = |people: &[Person]| -> Vec<Vec<Sport>> { people.iter().map(|p| p.favorite_sports.clone()).collect() };
+        let kabaddi = Sport::Other("Kabaddi".to_string());
+        assert_eq!(
+            sports(&Person::read_from_csv(&path).unwrap()),
+            [vec![Sport::Football, Sport::Football], vec![Sport::Soccer, kabaddi.clone()]]
+        );
+
+        // What `repair-sports --language en_GB` does
+        prefer_language(Some("en_GB"));
+        let repaired = Person::read_from_csv(&path);
+        prefer_language(None);
+        Person::write_to_csv(&path, &repaired.unwrap()).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(",soccer; football,"), "{}", content);
+        assert!(content.contains(",soccer; Kabaddi,"), "{}", content);
+        assert_eq!(
+            sports(&Person::read_from_csv(&path).unwrap()),
+            [vec![Sport::Soccer, Sport::Football], vec![Sport::Soccer, kabaddi]]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+use crate::atomic_file::write_atomically;
+use crate::constants::CSV_HEADERS;
+use crate::store::companion_path;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Type of a custom field, and what its values may be.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    /// A `YYYY-MM-DD` date
+    Date,
+    /// `true` or `false`
+    Boolean,
+    /// One of a fixed list of options
+    Enum { options: Vec<String> },
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::String => write!(f, "string"),
+            FieldType::Integer => write!(f, "integer"),
+            FieldType::Date => write!(f, "date"),
+            FieldType::Boolean => write!(f, "boolean"),
+            FieldType::Enum { options } => write!(f, "enum ({})", options.join(", ")),
+        }
+    }
+}
+
+/// A custom field that can be set on every person.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDefinition {
+    /// Key in `Person::custom` and column name in CSV files
+    pub name: String,
+    /// Shown instead of the name in prompts and tables, if set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub field_type: FieldType,
+}
+
+impl FieldDefinition {
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Describes the values the field accepts, for prompts.
+    pub fn hint(&self) -> String {
+        match &self.field_type {
+            FieldType::String => "text".to_string(),
+            FieldType::Integer => "whole number".to_string(),
+            FieldType::Date => "YYYY-MM-DD".to_string(),
+            FieldType::Boolean => "yes/no".to_string(),
+            FieldType::Enum { options } => options.join("/"),
+        }
+    }
+
+    /// Checks a value typed by the user and returns it in its stored form. Booleans are
+    /// stored as `true`/`false` and enum values with the case of the option. Blank input
+    /// is an empty value, meaning the field is not set.
+    pub fn parse_value(&self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        match &self.field_type {
+            FieldType::String => Ok(input.to_string()),
+            FieldType::Integer => input
+                .parse::<i64>()
+                .map(|n| n.to_string())
+                .map_err(|_| format!("{}: '{}' is not a whole number", self.label(), input)),
+            FieldType::Date => NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .map_err(|_| format!("{}: '{}' is not a date, expected YYYY-MM-DD", self.label(), input)),
+            FieldType::Boolean => match input.to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Ok("true".to_string()),
+                "false" | "no" | "n" | "0" => Ok("false".to_string()),
+                _ => Err(format!("{}: '{}' is not yes or no", self.label(), input)),
+            },
+            FieldType::Enum { options } => options
+                .iter()
+                .find(|o| o.eq_ignore_ascii_case(input))
+                .cloned()
+                .ok_or_else(|| format!("{}: '{}' is not one of {}", self.label(), input, options.join(", "))),
+        }
+    }
+}
+
+/// The custom fields of a database, kept in a `<database>.fields.json` file next to it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    #[serde(default)]
+    pub fields: Vec<FieldDefinition>,
+}
+
+impl FieldSchema {
+    /// Path of the schema file for the database at `database`.
+    pub fn path_for<P: AsRef<Path>>(database: P) -> PathBuf {
+        companion_path(database, ".fields.json")
+    }
+
+    /// Reads the schema for the database at `database`. A missing file is an empty schema.
+    pub fn load_for<P: AsRef<Path>>(database: P) -> Result<FieldSchema, Box<dyn Error>> {
+        let path = FieldSchema::path_for(&database);
+        if !path.exists() {
+            return Ok(FieldSchema::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        let schema: FieldSchema =
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        log::info!("Read {} custom fields from {}", schema.fields.len(), path.display());
+        Ok(schema)
+    }
+
+    /// Writes the schema next to the database at `database`.
+    pub fn save_for<P: AsRef<Path>>(&self, database: P) -> Result<(), Box<dyn Error>> {
+        let path = FieldSchema::path_for(&database);
+        write_atomically(&path, |file| {
+            serde_json::to_writer_pretty(&mut *file, self)?;
+            file.write_all(b"\n")?;
+            Ok(())
+        })?;
+        log::info!("Wrote {} custom fields to {}", self.fields.len(), path.display());
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FieldDefinition> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Adds a field. Names are lower case letters, digits and underscores, and cannot be
+    /// one of the built-in columns.
+    pub fn add(&mut self, field: FieldDefinition) -> Result<(), Box<dyn Error>> {
+        let valid_name = !field.name.is_empty()
+            && field
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "Invalid field name '{}': use lower case letters, digits and underscores",
+                field.name
+            )
+            .into());
+        }
+        if CSV_HEADERS.contains(&field.name.as_str()) || self.get(&field.name).is_some() {
+            return Err(format!("A field named '{}' already exists", field.name).into());
+        }
+        if let FieldType::Enum { options } = &field.field_type {
+            if options.is_empty() {
+                return Err(format!("Field '{}' needs at least one option", field.name).into());
+            }
+        }
+        self.fields.push(field);
+        Ok(())
+    }
+
+    /// Removes the definition of a field. The values set on people are not touched here;
+    /// `fields remove` deletes them from every person.
+    pub fn remove(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let index = self
+            .fields
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| format!("No field named '{}'", name))?;
+        self.fields.remove(index);
+        Ok(())
+    }
+
+    /// Adds the fields of `other` that this schema does not have yet.
+    pub fn merge(&mut self, other: &FieldSchema) {
+        for field in &other.fields {
+            if self.get(&field.name).is_none() {
+                self.fields.push(field.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, field_type: FieldType) -> FieldDefinition {
+        FieldDefinition { name: name.to_string(), label: None, field_type }
+    }
+
+    fn temp_database(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("custom-fields-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("people.csv");
+        (dir, database)
+    }
+
+    #[test]
+    fn schema_is_saved_next_to_the_database() {
+        let (dir, database) = temp_database("round-trip");
+        assert_eq!(FieldSchema::path_for(&database), dir.join("people.csv.fields.json"));
+
+        let mut schema = FieldSchema::default();
+        schema.add(field("shirt_size", FieldType::Enum { options: vec!["S".into(), "M".into(), "L".into()] })).unwrap();
+        schema
+            .add(FieldDefinition { label: Some("Member since".to_string()), ..field("member_since", FieldType::Date) })
+            .unwrap();
+        schema.add(field("lessons", FieldType::Integer)).unwrap();
+        schema.save_for(&database).unwrap();
+
+        let content = fs::read_to_string(FieldSchema::path_for(&database)).unwrap();
+        assert!(content.contains("\"type\": \"enum\""), "{}", content);
+        assert_eq!(FieldSchema::load_for(&database).unwrap(), schema);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_schema_is_empty_and_malformed_schema_is_an_error() {
+        let (dir, database) = temp_database("missing");
+        assert_eq!(FieldSchema::load_for(&database).unwrap(), FieldSchema::default());
+
+        let path = FieldSchema::path_for(&database);
+        fs::write(&path, "{\"fields\": [{\"name\": \"size\"").unwrap();
+        let error = FieldSchema::load_for(&database).unwrap_err().to_string();
+        assert!(error.starts_with(&path.display().to_string()), "{}", error);
+
+        fs::write(&path, "{\"fields\": [{\"name\": \"size\", \"type\": \"colour\"}]}").unwrap();
+        assert!(FieldSchema::load_for(&database).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn values_are_checked_against_the_field_type() {
+        let integer = field("lessons", FieldType::Integer);
+        assert_eq!(integer.parse_value(" 12 "), Ok("12".to_string()));
+        assert_eq!(integer.parse_value("twelve"), Err("lessons: 'twelve' is not a whole number".to_string()));
+        assert_eq!(integer.parse_value("  "), Ok(String::new()));
+
+        let date = FieldDefinition {
+            label: Some("Member since".to_string()),
+            ..field("member_since", FieldType::Date)
+        };
+        assert_eq!(date.parse_value("2024-02-29"), Ok("2024-02-29".to_string()));
+        assert_eq!(
+            date.parse_value("2023-02-29"),
+            Err("Member since: '2023-02-29' is not a date, expected YYYY-MM-DD".to_string())
+        );
+
+        let boolean = field("insured", FieldType::Boolean);
+        assert_eq!(boolean.parse_value("Yes"), Ok("true".to_string()));
+        assert_eq!(boolean.parse_value("0"), Ok("false".to_string()));
+        assert!(boolean.parse_value("maybe").is_err());
+
+        let size = field("shirt_size", FieldType::Enum { options: vec!["S".into(), "M".into(), "XL".into()] });
+        assert_eq!(size.parse_value("xl"), Ok("XL".to_string()));
+        assert_eq!(size.parse_value("L"), Err("shirt_size: 'L' is not one of S, M, XL".to_string()));
+
+        assert_eq!(field("nickname", FieldType::String).parse_value(" Sam "), Ok("Sam".to_string()));
+    }
+
+    #[test]
+    fn invalid_fields_are_not_added() {
+        let mut schema = FieldSchema::default();
+        assert!(schema.add(field("Shirt Size", FieldType::String)).is_err());
+        assert!(schema.add(field("", FieldType::String)).is_err());
+        assert!(schema.add(field("notes", FieldType::String)).is_err());
+        assert!(schema.add(field("size", FieldType::Enum { options: Vec::new() })).is_err());
+        schema.add(field("size", FieldType::String)).unwrap();
+        assert!(schema.add(field("size", FieldType::Integer)).is_err());
+        assert_eq!(schema.fields, [field("size", FieldType::String)]);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::person::Person;
+use crate::phonetic::sound_similarity;
+use crate::text::name_words;
+
+/// Two people who may be the same person entered twice.
+pub struct Duplicate {
+    /// Indices of the two people, the first one lower
+    pub first: usize,
+    pub second: usize,
+    /// Why they look alike, e.g. `names sound alike`
+    pub reasons: Vec<String>,
+}
+
+/// Finds pairs of people who are probably the same person: their first and last names share
+/// a Double Metaphone code and their dates of birth do not contradict each other, or they
+/// share an email address or phone number. Pairs with the most reasons come first.
+pub fn find_duplicates(people: &[Person]) -> Vec<Duplicate> {
+    let mut found = Vec::new();
+    for (first, a) in people.iter().enumerate() {
+        for (second, b) in people.iter().enumerate().skip(first + 1) {
+            let reasons = reasons(a, b);
+            if !reasons.is_empty() {
+                found.push(Duplicate { first, second, reasons });
+            }
+        }
+    }
+    found.sort_by_key(|d| std::cmp::Reverse(d.reasons.len()));
+    found
+}
+
+fn reasons(a: &Person, b: &Person) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let same_name = name_words(&a.first_name) == name_words(&b.first_name)
+        && name_words(&a.last_name) == name_words(&b.last_name);
+    // Soundex alone matches too many names, such as Pfister and Pester, so a shared Double
+    // Metaphone code is needed
+    let sound_alike = |a: &str, b: &str| sound_similarity(a, b).is_some_and(|similarity| similarity >= 2);
+    let names_alike = same_name
+        || (sound_alike(&a.first_name, &b.first_name) && sound_alike(&a.last_name, &b.last_name));
+    if names_alike && a.date_of_birth.agrees_with(&b.date_of_birth) {
+        reasons.push(if same_name { "same name" } else { "names sound alike" }.to_string());
+        if a.date_of_birth.full().is_some() && a.date_of_birth == b.date_of_birth {
+            reasons.push("same date of birth".to_string());
+        }
+    }
+    for email in &a.emails {
+        if b.emails.iter().any(|other| other.eq_ignore_ascii_case(email)) {
+            reasons.push(format!("same email address {}", email));
+        }
+    }
+    for phone in &a.phones {
+        if b.phones.contains(phone) {
+            reasons.push(format!("same phone number {}", phone));
+        }
+    }
+    reasons
+}
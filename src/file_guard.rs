@@ -0,0 +1,272 @@
+use crate::store::companion_path;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Snapshot of a database file taken when it was loaded, used to detect
+/// changes made by another process before overwriting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+
+impl FileFingerprint {
+    /// Takes a fingerprint of the file at `path`.
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read(&path)?;
+        let metadata = fs::metadata(&path)?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Ok(FileFingerprint {
+            modified: metadata.modified().ok(),
+            len: content.len() as u64,
+            hash: hasher.finish(),
+        })
+    }
+
+    /// Returns true if the file no longer has the content it had when fingerprinted.
+    ///
+    /// A file that was only touched (new mtime, same content) is not considered changed.
+    /// A file that has since been deleted is.
+    pub fn has_changed<P: AsRef<Path>>(&self, path: P) -> bool {
+        match FileFingerprint::of(path) {
+            Ok(current) => current.len != self.len || current.hash != self.hash,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Advisory lock file (`<database>.lock`) that tells other editors the database is open.
+///
+/// The lock is not enforced; it only lets editors warn each other. The first editor keeps it
+/// until it is dropped, and it is removed then unless someone else has replaced the file.
+/// A lock left by an editor that is no longer running, or taken more than a day ago, is
+/// stale and is taken over by the next editor.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    owner: String,
+}
+
+/// What happened when trying to lock a database.
+#[derive(Debug)]
+pub enum LockStatus {
+    Acquired(LockFile),
+    /// Another editor holds the lock, described by its owner line
+    HeldBy(String),
+}
+
+// Locks older than this are stale even if their process seems to be running, since process
+// IDs are reused
+const STALE_AFTER_HOURS: i64 = 24;
+
+impl LockFile {
+    fn lock_path(database: &Path) -> PathBuf {
+        companion_path(database, ".lock")
+    }
+
+    /// Returns who currently holds the lock on `database`, if anyone. Stale locks are ignored.
+    pub fn current_owner<P: AsRef<Path>>(database: P) -> Option<String> {
+        Self::read_owner(database.as_ref()).filter(|owner| !is_stale(owner))
+    }
+
+    fn read_owner(database: &Path) -> Option<String> {
+        fs::read_to_string(Self::lock_path(database))
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Creates the lock file for `database`, taking over a stale lock. If another editor
+    /// holds the lock, it is left in place and its owner is returned instead.
+    pub fn acquire<P: AsRef<Path>>(database: P) -> io::Result<LockStatus> {
+        let path = Self::lock_path(database.as_ref());
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => Self::write_owner(path, file).map(LockStatus::Acquired),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                match Self::read_owner(database.as_ref()) {
+                    Some(owner) if !is_stale(&owner) => Ok(LockStatus::HeldBy(owner)),
+                    owner => {
+                        log::info!("Taking over stale lock {}: {}", path.display(), owner.unwrap_or_default());
+                        Self::take_over(database).map(LockStatus::Acquired)
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Locks `database` whoever holds the lock now, for when the other editor is known to
+    /// be closed.
+    pub fn take_over<P: AsRef<Path>>(database: P) -> io::Result<LockFile> {
+        let path = Self::lock_path(database.as_ref());
+        let file = fs::File::create(&path)?;
+        Self::write_owner(path, file)
+    }
+
+    // Writes who holds the lock to the newly created lock file at `path`
+    fn write_owner(path: PathBuf, mut file: fs::File) -> io::Result<LockFile> {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let owner = format!(
+            "{} (pid {}, since {})",
+            user,
+            std::process::id(),
+            chrono::Local::now().format(SINCE_FORMAT)
+        );
+        if let Err(e) = file.write_all(owner.as_bytes()) {
+            // An empty lock would warn every other editor without saying who holds it
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(LockFile { path, owner })
+    }
+}
+
+const SINCE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// Whether the editor described by `owner` is no longer running or locked the file long ago.
+// Owners that cannot be read are not stale, so that they still get a warning.
+fn is_stale(owner: &str) -> bool {
+    let field = |name: &str| {
+        let start = owner.find(name)? + name.len();
+        let rest = &owner[start..];
+        Some(rest[..rest.find([',', ')']).unwrap_or(rest.len())].trim())
+    };
+    let since = field("since ").and_then(|since| chrono::NaiveDateTime::parse_from_str(since, SINCE_FORMAT).ok());
+    if since.is_some_and(|since| chrono::Local::now().naive_local() - since > chrono::Duration::hours(STALE_AFTER_HOURS)) {
+        return true;
+    }
+    // Only Linux can tell cheaply whether a process is running
+    let pid = field("(pid ").and_then(|pid| pid.parse::<u32>().ok());
+    match pid {
+        Some(pid) if Path::new("/proc/self").exists() => !Path::new("/proc").join(pid.to_string()).exists(),
+        _ => false,
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if fs::read_to_string(&self.path).is_ok_and(|s| s.trim() == self.owner) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for one test, with the path of a database in it
+    fn database(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-guard-test-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("people.csv");
+        fs::write(&path, "id,first_name\n0,Ann\n").unwrap();
+        path
+    }
+
+    fn cleanup(database: &Path) {
+        fs::remove_dir_all(database.parent().unwrap()).unwrap();
+    }
+
+    fn since(hours_ago: i64) -> String {
+        (chrono::Local::now() - chrono::Duration::hours(hours_ago)).format(SINCE_FORMAT).to_string()
+    }
+
+    fn acquired(status: LockStatus) -> LockFile {
+        match status {
+            LockStatus::Acquired(lock) => lock,
+            LockStatus::HeldBy(owner) => panic!("Lock held by {}", owner),
+        }
+    }
+
+    #[test]
+    fn fingerprint_notices_changed_content_only() {
+        let path = database("fingerprint");
+        let fingerprint = FileFingerprint::of(&path).unwrap();
+        assert!(!fingerprint.has_changed(&path));
+
+        // Rewriting the same content is not a change
+        fs::write(&path, "id,first_name\n0,Ann\n").unwrap();
+        assert!(!fingerprint.has_changed(&path));
+
+        fs::write(&path, "id,first_name\n0,Anne\n").unwrap();
+        assert!(fingerprint.has_changed(&path));
+
+        fs::remove_file(&path).unwrap();
+        assert!(fingerprint.has_changed(&path));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn second_editor_sees_who_holds_the_lock() {
+        let path = database("held");
+        let lock = acquired(LockFile::acquire(&path).unwrap());
+        assert_eq!(LockFile::current_owner(&path), Some(lock.owner.clone()));
+        match LockFile::acquire(&path).unwrap() {
+            LockStatus::HeldBy(owner) => assert_eq!(owner, lock.owner),
+            LockStatus::Acquired(_) => panic!("Lock acquired twice"),
+        }
+
+        drop(lock);
+        assert!(!LockFile::lock_path(&path).exists());
+        assert_eq!(LockFile::current_owner(&path), None);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn old_locks_are_taken_over() {
+        let path = database("old");
+        let old = format!("bob (pid {}, since {})", std::process::id(), since(STALE_AFTER_HOURS + 1));
+        fs::write(LockFile::lock_path(&path), &old).unwrap();
+        assert_eq!(LockFile::current_owner(&path), None);
+
+        let lock = acquired(LockFile::acquire(&path).unwrap());
+        assert_ne!(lock.owner, old);
+        drop(lock);
+        cleanup(&path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn locks_of_editors_no_longer_running_are_taken_over() {
+        let path = database("dead");
+        fs::write(LockFile::lock_path(&path), format!("bob (pid {}, since {})", u32::MAX, since(0))).unwrap();
+        assert_eq!(LockFile::current_owner(&path), None);
+        drop(acquired(LockFile::acquire(&path).unwrap()));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn unreadable_owners_are_not_stale() {
+        let path = database("unreadable");
+        fs::write(LockFile::lock_path(&path), "someone else").unwrap();
+        assert!(matches!(LockFile::acquire(&path).unwrap(), LockStatus::HeldBy(owner) if owner == "someone else"));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn drop_leaves_a_lock_someone_else_took_over() {
+        let path = database("taken-over");
+        let lock = acquired(LockFile::acquire(&path).unwrap());
+        let other = format!("bob (pid {}, since {})", std::process::id(), since(0));
+        fs::write(LockFile::lock_path(&path), &other).unwrap();
+        drop(lock);
+        assert_eq!(LockFile::current_owner(&path), Some(other));
+
+        // Taking over a live lock replaces it, and the new holder removes it when done
+        let lock = LockFile::take_over(&path).unwrap();
+        assert_eq!(LockFile::current_owner(&path), Some(lock.owner.clone()));
+        drop(lock);
+        assert!(!LockFile::lock_path(&path).exists());
+        cleanup(&path);
+    }
+}
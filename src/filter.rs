@@ -0,0 +1,563 @@
+use crate::birth_date::BirthDate;
+use crate::constants::Sport;
+use crate::custom_fields::FieldSchema;
+use crate::person::Person;
+use crate::text::fold_diacritics;
+use chrono::{Datelike, NaiveDate};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+
+/// A condition on people, such as `age > 30 and (sport = tennis or sport = golf)`.
+///
+/// Conditions compare a field with a value using `=`, `!=`, `<`, `<=`, `>`, `>=`, `~`
+/// (contains, or matches a `/regex/`) or `!~`, and are combined with `and`, `or`, `not` and
+/// parentheses. Text is compared ignoring case. Fields with several values, such as `sport`
+/// or `tag`, match if any of the values does (`!=` and `!~` if none does). A condition on a
+/// date of birth or age that is not known is false, so `not age > 30` does include people
+/// whose age is not known. Parse errors give the column where the problem is.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Compare(Comparison),
+}
+
+/// A single `field op value` condition.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    field: FilterField,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FilterField {
+    Id,
+    FirstName,
+    LastName,
+    /// First name, last name or both
+    Name,
+    /// Age in whole years on the date ages are shown for
+    Age,
+    /// Date of birth, compared with a `YYYY-MM-DD` date or a year
+    Born,
+    /// Month of the birthday, 1 to 12
+    BirthMonth,
+    /// Any of the favorite sports
+    Sport,
+    PrimarySport,
+    Tag,
+    Email,
+    Phone,
+    Address,
+    Notes,
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Matches,
+    NotMatches,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Number(i64),
+    Date(BirthDate),
+    Pattern(Regex),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// A quoted string, never taken for a keyword
+    Quoted(String),
+    Pattern(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl FilterField {
+    fn parse(name: &str, schema: &FieldSchema) -> Result<FilterField, String> {
+        Ok(match name.to_lowercase().as_str() {
+            "id" => FilterField::Id,
+            "first_name" | "first" => FilterField::FirstName,
+            "last_name" | "last" => FilterField::LastName,
+            "name" => FilterField::Name,
+            "age" => FilterField::Age,
+            "born" | "date_of_birth" | "dob" => FilterField::Born,
+            "birth_month" => FilterField::BirthMonth,
+            "sport" | "sports" | "favorite_sport" | "favorite_sports" => FilterField::Sport,
+            "primary_sport" => FilterField::PrimarySport,
+            "tag" | "tags" => FilterField::Tag,
+            "email" | "emails" => FilterField::Email,
+            "phone" | "phones" => FilterField::Phone,
+            "address" => FilterField::Address,
+            "notes" => FilterField::Notes,
+            _ if schema.get(name).is_some() => FilterField::Custom(name.to_string()),
+            _ => return Err(format!("Unknown field '{}'", name)),
+        })
+    }
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Matches | Op::NotMatches => false,
+        }
+    }
+
+    // Whether the op is true when no value of a list satisfies its opposite
+    fn is_negative(self) -> bool {
+        matches!(self, Op::Ne | Op::NotMatches)
+    }
+
+    fn positive(self) -> Op {
+        match self {
+            Op::Ne => Op::Eq,
+            Op::NotMatches => Op::Matches,
+            op => op,
+        }
+    }
+}
+
+// Splits `input` into tokens, each with the column it starts at, counting from 1
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let length = input.chars().count();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let column = length - chars.clone().count() + 1;
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        chars.next();
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Op(Op::Matches),
+            '=' => {
+                // `==` is the same as `=`
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' if chars.next_if_eq(&'~').is_some() => Token::Op(Op::NotMatches),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err(at(column, format!("Missing closing {} after '{}'", c, text))),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            '/' => {
+                let mut pattern = String::new();
+                loop {
+                    match chars.next() {
+                        Some('/') => break,
+                        // `\/` is a slash within the pattern
+                        Some('\\') if chars.peek() == Some(&'/') => pattern.push(chars.next().unwrap()),
+                        Some(ch) => pattern.push(ch),
+                        None => return Err(at(column, format!("Missing closing / after '{}'", pattern))),
+                    }
+                }
+                Token::Pattern(pattern)
+            }
+            '!' => return Err(at(column, "Expected != or !~ after !".to_string())),
+            _ => {
+                let mut word = c.to_string();
+                while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace() && !"()=!<>~\"'".contains(*ch)) {
+                    word.push(ch);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((token, column));
+    }
+    Ok(tokens)
+}
+
+// Adds the column of a problem to its message
+fn at(column: usize, message: String) -> String {
+    format!("{} at column {}", message, column)
+}
+
+// Recursive descent over the tokens: `or` binds loosest, then `and`, then `not`
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    // Column just after the input, where a missing token would go
+    end: usize,
+    schema: &'a FieldSchema,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    // An error about the token last read by `next`
+    fn error(&self, message: String) -> String {
+        let column = self.tokens.get(self.position - 1).map_or(self.end, |(_, column)| *column);
+        at(column, message)
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.next_is_keyword("or") {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.not()?;
+        while self.next_is_keyword("and") {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, String> {
+        if self.next_is_keyword("not") {
+            self.next();
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => Err(self.error("Missing )".to_string())),
+                }
+            }
+            Some(Token::Word(field)) => self.comparison(&field),
+            Some(token) => Err(self.error(format!("Expected a field name, found {}", describe(&token)))),
+            None => Err(self.error("Expected a condition such as age > 30".to_string())),
+        }
+    }
+
+    fn comparison(&mut self, field_name: &str) -> Result<Filter, String> {
+        let field = FilterField::parse(field_name, self.schema).map_err(|e| self.error(e))?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(token) => {
+                return Err(self.error(format!("Expected a comparison after {}, found {}", field_name, describe(&token))))
+            }
+            None => return Err(self.error(format!("Expected a comparison after {}", field_name))),
+        };
+        let is_match = matches!(op, Op::Matches | Op::NotMatches);
+        if is_match && matches!(field, FilterField::Age | FilterField::BirthMonth | FilterField::Born) {
+            return Err(self.error(format!("~ cannot be used with {}", field_name)));
+        }
+        if matches!(field, FilterField::Sport | FilterField::PrimarySport) && !matches!(op.positive(), Op::Eq | Op::Matches) {
+            return Err(self.error("Sports can only be compared with =, !=, ~ or !~".to_string()));
+        }
+        let value = match self.next() {
+            Some(Token::Word(text)) | Some(Token::Quoted(text)) => text,
+            Some(Token::Pattern(pattern)) if is_match => {
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| self.error(format!("Invalid pattern /{}/: {}", pattern, e)))?;
+                return Ok(Filter::Compare(Comparison { field, op, value: Value::Pattern(regex) }));
+            }
+            Some(Token::Pattern(pattern)) => return Err(self.error(format!("/{}/ can only be used with ~ or !~", pattern))),
+            Some(token) => return Err(self.error(format!("Expected a value after {}, found {}", field_name, describe(&token)))),
+            None => return Err(self.error(format!("Expected a value after {}", field_name))),
+        };
+
+        let value = match field {
+            FilterField::Age | FilterField::BirthMonth => value
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| self.error(format!("{} needs a whole number, not '{}'", field_name, value)))?,
+            FilterField::Born => match BirthDate::parse(&value) {
+                Some(date @ (BirthDate::Full(_) | BirthDate::Year(_))) => Value::Date(date),
+                _ => return Err(self.error(format!("{} needs a date (YYYY-MM-DD) or a year, not '{}'", field_name, value))),
+            },
+            _ => Value::Text(value),
+        };
+        Ok(Filter::Compare(Comparison { field, op, value }))
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) => format!("'{}'", w),
+        Token::Quoted(q) => format!("\"{}\"", q),
+        Token::Pattern(p) => format!("/{}/", p),
+        Token::Op(_) => "a comparison".to_string(),
+        Token::Open => "(".to_string(),
+        Token::Close => ")".to_string(),
+    }
+}
+
+impl Filter {
+    /// Parses a filter expression. Custom fields of `schema` can be used by name.
+    pub fn parse(input: &str, schema: &FieldSchema) -> Result<Filter, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            end: input.chars().count() + 1,
+            schema,
+        };
+        let filter = parser.or()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(token) => Err(parser.error(format!("Unexpected {}; combine conditions with and/or", describe(&token)))),
+        }
+    }
+
+    /// Whether `person` meets the condition, with ages as of `as_of`.
+    pub fn matches(&self, person: &Person, as_of: NaiveDate) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(person, as_of) && b.matches(person, as_of),
+            Filter::Or(a, b) => a.matches(person, as_of) || b.matches(person, as_of),
+            Filter::Not(filter) => !filter.matches(person, as_of),
+            Filter::Compare(comparison) => comparison.matches(person, as_of),
+        }
+    }
+}
+
+impl Comparison {
+    fn matches(&self, person: &Person, as_of: NaiveDate) -> bool {
+        match &self.field {
+            FilterField::Id => self.any_text(std::iter::once(person.id.clone())),
+            FilterField::FirstName => self.any_text(std::iter::once(person.first_name.clone())),
+            FilterField::LastName => self.any_text(std::iter::once(person.last_name.clone())),
+            FilterField::Name => self.any_text([
+                person.first_name.clone(),
+                person.last_name.clone(),
+                format!("{} {}", person.first_name, person.last_name),
+            ]),
+            FilterField::Address => self.any_text(std::iter::once(person.address.clone())),
+            FilterField::Notes => self.any_text(std::iter::once(person.notes.clone())),
+            FilterField::Tag => self.any_text(person.tags.iter().cloned()),
+            FilterField::Email => self.any_text(person.emails.iter().cloned()),
+            FilterField::Phone => self.any_text(person.phones.iter().cloned()),
+            FilterField::Sport => self.any_sport(&person.favorite_sports),
+            FilterField::PrimarySport => self.any_sport(person.primary_sport()),
+            FilterField::Custom(name) => {
+                let value = person.custom.get(name).cloned().unwrap_or_default();
+                // A field that is not set only equals ""
+                if value.is_empty() && !matches!(&self.value, Value::Text(text) if text.is_empty()) {
+                    return self.any_text(std::iter::empty());
+                }
+                match (&self.value, value.trim().parse::<i64>()) {
+                    // Numbers compare as numbers, so that 9 < 10
+                    (Value::Text(text), Ok(number)) if !matches!(self.op.positive(), Op::Matches) => {
+                        match text.trim().parse::<i64>() {
+                            Ok(wanted) => self.op.holds(number.cmp(&wanted)),
+                            Err(_) => self.any_text(std::iter::once(value)),
+                        }
+                    }
+                    _ => self.any_text(std::iter::once(value)),
+                }
+            }
+            FilterField::Age => {
+                let Value::Number(wanted) = self.value else { return false };
+                let (youngest, oldest) = match person.date_of_birth {
+                    BirthDate::Full(_) => match person.age_on(as_of) {
+                        Some(age) => (age as i64, age as i64),
+                        None => return false,
+                    },
+                    // Either age is possible, so both must meet the condition
+                    BirthDate::Year(year) if as_of.year() >= year => {
+                        ((as_of.year() - year - 1).max(0) as i64, (as_of.year() - year) as i64)
+                    }
+                    _ => return false,
+                };
+                self.op.holds(youngest.cmp(&wanted)) && self.op.holds(oldest.cmp(&wanted))
+            }
+            FilterField::BirthMonth => {
+                let Value::Number(wanted) = self.value else { return false };
+                let month = match person.date_of_birth {
+                    BirthDate::Full(date) => date.month(),
+                    BirthDate::MonthDay { month, .. } => month,
+                    _ => return false,
+                };
+                self.op.holds((month as i64).cmp(&wanted))
+            }
+            FilterField::Born => {
+                let Value::Date(wanted) = self.value else { return false };
+                let ordering = match (person.date_of_birth, wanted) {
+                    (BirthDate::Full(date), BirthDate::Full(wanted)) => date.cmp(&wanted),
+                    (BirthDate::Full(date), BirthDate::Year(year)) => date.year().cmp(&year),
+                    (BirthDate::Year(year), BirthDate::Year(wanted)) => year.cmp(&wanted),
+                    // Only a different year decides a comparison with a full date
+                    (BirthDate::Year(year), BirthDate::Full(wanted)) if year != wanted.year() => year.cmp(&wanted.year()),
+                    _ => return false,
+                };
+                self.op.holds(ordering)
+            }
+        }
+    }
+
+    // Whether any of `values` meets the condition, or none does for `!=` and `!~`
+    fn any_text(&self, values: impl IntoIterator<Item = String>) -> bool {
+        let op = self.op.positive();
+        let found = values.into_iter().any(|value| match (&self.value, op) {
+            (Value::Pattern(regex), _) => regex.is_match(&value),
+            (Value::Text(text), Op::Matches) => fold(&value).contains(&fold(text)),
+            (Value::Text(text), op) => op.holds(fold(&value).cmp(&fold(text))),
+            _ => false,
+        });
+        found != self.op.is_negative()
+    }
+
+    // Sports are equal when they are the same sport, under any of its names
+    fn any_sport<'a>(&self, sports: impl IntoIterator<Item = &'a Sport>) -> bool {
+        let found = sports.into_iter().any(|sport| match &self.value {
+            Value::Pattern(regex) => regex.is_match(&sport.to_string()) || regex.is_match(&sport.canonical()),
+            Value::Text(text) if self.op.positive() == Op::Matches => {
+                fold(&sport.to_string()).contains(&fold(text)) || fold(&sport.canonical()).contains(&fold(text))
+            }
+            Value::Text(text) => *sport == Sport::from_string(text),
+            _ => false,
+        });
+        found != self.op.is_negative()
+    }
+}
+
+// Lower case without accents, for comparing text typed by people
+fn fold(s: &str) -> String {
+    fold_diacritics(s).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(first: &str, last: &str, date_of_birth: BirthDate, tags: &[&str]) -> Person {
+        let mut person = Person::new(first.to_string(), last.to_string(), date_of_birth, vec![Sport::Tennis]);
+        person.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        person
+    }
+
+    fn born(year: i32, month: u32, day: u32) -> BirthDate {
+        BirthDate::Full(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 15).unwrap()
+    }
+
+    fn matches(input: &str, person: &Person) -> bool {
+        Filter::parse(input, &FieldSchema::default()).unwrap().matches(person, today())
+    }
+
+    fn error(input: &str) -> String {
+        Filter::parse(input, &FieldSchema::default()).unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let ann = person("Ann", "Miller", born(1990, 1, 1), &[]);
+        // `a or (b and c)`, not `(a or b) and c`
+        assert!(matches("first_name = ann or last_name = smith and tag = coach", &ann));
+        assert!(!matches("(first_name = ann or last_name = smith) and tag = coach", &ann));
+        assert!(matches("tag = coach and last_name = smith or first_name = ann", &ann));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let ann = person("Ann", "Miller", born(1990, 1, 1), &["coach"]);
+        assert!(!matches("not tag = coach and first_name = ann", &ann));
+        assert!(matches("not (tag = coach and first_name = bob)", &ann));
+        assert!(matches("not not tag = coach", &ann));
+    }
+
+    #[test]
+    fn quoted_values_and_patterns() {
+        let ann = person("Ann", "Miller Ríos", born(1990, 1, 1), &["team lead"]);
+        assert!(matches("name = \"Ann Miller Ríos\"", &ann));
+        assert!(matches("tag = 'team lead'", &ann));
+        assert!(matches("last_name ~ 'rios'", &ann));
+        assert!(matches("tag = \"it's\" or name ~ /^ann m/", &ann));
+        assert!(matches("last_name ~ /r\\/?íos$/", &ann));
+        assert!(!matches("last_name !~ \"miller\"", &ann));
+    }
+
+    #[test]
+    fn ages_on_full_and_year_only_dates_of_birth() {
+        let full = person("Ann", "Miller", born(1990, 6, 16), &[]);
+        assert!(matches("age = 33", &full));
+        assert!(matches("born < 1990-06-17 and born >= 1990", &full));
+
+        // Born in 1990, so 33 or 34: only conditions both ages meet hold
+        let year = person("Bob", "Smith", BirthDate::Year(1990), &[]);
+        assert!(matches("age > 32 and age < 35", &year));
+        assert!(!matches("age > 33", &year));
+        assert!(!matches("age = 34", &year));
+        assert!(matches("born = 1990", &year));
+        assert!(!matches("born < 1990-06-01", &year));
+        assert!(matches("born < 1991-01-01", &year));
+    }
+
+    #[test]
+    fn unknown_ages_and_birthdays() {
+        let unknown = person("Ann", "Miller", BirthDate::Unknown, &[]);
+        assert!(!matches("age > 30", &unknown));
+        assert!(!matches("age <= 30", &unknown));
+        assert!(!matches("born = 1990", &unknown));
+        assert!(!matches("birth_month = 6", &unknown));
+        // A condition that is not known is false, so its opposite holds
+        assert!(matches("not age > 30", &unknown));
+
+        let month_day = person("Bob", "Smith", BirthDate::MonthDay { month: 6, day: 1 }, &[]);
+        assert!(matches("birth_month = 6", &month_day));
+        assert!(!matches("age > 30", &month_day));
+        assert!(matches("not age > 30", &month_day));
+    }
+
+    #[test]
+    fn errors_give_the_column() {
+        assert_eq!(error("agee > 30"), "Unknown field 'agee' at column 1");
+        assert_eq!(error("age > 30 and"), "Expected a condition such as age > 30 at column 13");
+        assert_eq!(error("age > thirty"), "age needs a whole number, not 'thirty' at column 7");
+        assert_eq!(error("(age > 30"), "Missing ) at column 10");
+        assert_eq!(error("age > 30 tag = x"), "Unexpected 'tag'; combine conditions with and/or at column 10");
+        assert_eq!(error("name = 'Ann"), "Missing closing ' after 'Ann' at column 8");
+        assert_eq!(error("age ! 30"), "Expected != or !~ after ! at column 5");
+        assert!(error("name ~ /(/").ends_with("at column 8"));
+        // Columns count characters, not bytes
+        assert_eq!(error("name = José ørn"), "Unexpected 'ørn'; combine conditions with and/or at column 13");
+    }
+}
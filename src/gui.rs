@@ -1,33 +1,284 @@
 use gtk::prelude::*;
-use gtk::{Application, ApplicationWindow, Label, Orientation, TreeView, ListStore, TreeViewColumn, CellRendererText, SelectionMode, Dialog, Entry, FileChooserDialog, FileChooserAction, ResponseType, Box as GtkBox, Button as GtkButton, ComboBoxText, ScrolledWindow, TextView};
+use gtk::{Application, ApplicationWindow, Label, Orientation, TreeView, ListStore, TreeViewColumn, CellRendererText, SelectionMode, Dialog, Entry, FileChooserDialog, FileChooserAction, ResponseType, Box as GtkBox, Button as GtkButton, CheckButton, ComboBoxText, ScrolledWindow, SearchEntry, TextView, TreeModelFilter, TreeModelSort};
 use gtk::gio::ApplicationFlags;
 use log;
 use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use gettextrs::gettext;
 use gtk::glib;
-use regex;
 
-mod person;
+mod atomic_file;
+mod birth_date;
 mod constants;
-use person::Person;
+mod custom_fields;
+mod file_guard;
+mod filter;
+mod ical;
+mod json_store;
+mod migrate;
+mod person;
+mod phonetic;
+mod relations;
+mod search;
+mod sport_catalog;
+mod sqlite_store;
+mod store;
+mod text;
+mod validation;
+mod vcard_store;
+use custom_fields::{FieldDefinition, FieldSchema};
+use file_guard::{FileFingerprint, LockFile, LockStatus};
+use filter::Filter;
+use person::{add_person, edit_person, merge_people, tag_counts, today, IdStrategy, Person};
+use relations::{related_people, retain_valid, Relation};
+use search::search;
+use validation::{parse_date_of_birth, validate_change, Field, ValidationError};
+use store::{export_to, import_from, open_store, LoadProblem};
 use constants::{APP_ID, APP_NAME, GUI_TABLE_HEADER_COLUMNS, Sport};
 
+// Custom dialog responses for a file that changed on disk
+const RESPONSE_RELOAD: u16 = 1;
+const RESPONSE_OVERWRITE: u16 = 2;
+const RESPONSE_MERGE: u16 = 3;
+// List store column holding each person's tags, separated by ", "
+const TAGS_COLUMN: u32 = 5;
+// Hidden list store column holding each person's rank by date of birth
+const AGE_SORT_COLUMN: u32 = 6;
+// List store column of the first custom field; the others follow it
+const FIRST_CUSTOM_COLUMN: u32 = 7;
+
+// Dialog response for saving records that could not be loaded
+const RESPONSE_QUARANTINE: u16 = 4;
+// Dialog response for taking over the lock of another editor
+const RESPONSE_TAKE_OVER_LOCK: u16 = 5;
+
 // Global state to store loaded people
 struct AppState {
     people: Vec<Person>,
     list_store: ListStore,
     tree_view: Rc<TreeView>,
-    last_file: Option<std::path::PathBuf>,
+    last_file: Option<PathBuf>,
+    // People as last loaded from or saved to `last_file`, used for merging
+    base_people: Vec<Person>,
+    fingerprint: Option<FileFingerprint>,
+    lock: Option<LockFile>,
+    // Custom fields of `last_file`, shown as extra columns
+    schema: FieldSchema,
+    // Filters `list_store` down to the people with every tag in `tag_filter` and, when there
+    // is a filter expression or search text, to the IDs in `matching_ids`
+    filter_model: TreeModelFilter,
+    tag_filter: Rc<RefCell<BTreeSet<String>>>,
+    filter: Option<Filter>,
+    search_text: String,
+    matching_ids: Rc<RefCell<Option<HashSet<String>>>>,
+    tag_sidebar: GtkBox,
+    // Relationships between the people of `last_file`
+    relations: Vec<Relation>,
 }
 
 impl AppState {
-    fn new(list_store: ListStore, tree_view: &Rc<TreeView>) -> Self {
+    fn new(list_store: ListStore, tree_view: &Rc<TreeView>, tag_sidebar: &GtkBox) -> Self {
+        let tag_filter = Rc::new(RefCell::new(BTreeSet::new()));
+        let matching_ids = Rc::new(RefCell::new(None));
+        let filter_model = Self::attach_model(&list_store, tree_view, &tag_filter, &matching_ids);
         Self {
             people: Vec::new(),
             list_store,
             tree_view: tree_view.clone(),
             last_file: None,
+            base_people: Vec::new(),
+            fingerprint: None,
+            lock: None,
+            schema: FieldSchema::default(),
+            filter_model,
+            tag_filter,
+            filter: None,
+            search_text: String::new(),
+            matching_ids,
+            tag_sidebar: tag_sidebar.clone(),
+            relations: Vec::new(),
+        }
+    }
+
+    // Shows `list_store` in the tree view, filtered by tag and filter expression and sortable
+    // by any column
+    fn attach_model(
+        list_store: &ListStore,
+        tree_view: &TreeView,
+        tag_filter: &Rc<RefCell<BTreeSet<String>>>,
+        matching_ids: &Rc<RefCell<Option<HashSet<String>>>>,
+    ) -> TreeModelFilter {
+        let filter_model = TreeModelFilter::new(list_store, None);
+        let tag_filter = tag_filter.clone();
+        let matching_ids = matching_ids.clone();
+        filter_model.set_visible_func(move |model, iter| {
+            let selected = tag_filter.borrow();
+            let tags = model.get::<Option<String>>(iter, TAGS_COLUMN as i32).unwrap_or_default();
+            let id = model.get::<Option<String>>(iter, 0).unwrap_or_default();
+            // The "No people loaded" row has no ID and is always shown
+            selected.iter().all(|t| tags.split(", ").any(|tag| tag == t))
+                && (id.is_empty() || matching_ids.borrow().as_ref().is_none_or(|ids| ids.contains(&id)))
+        });
+        tree_view.set_model(Some(&TreeModelSort::with_model(&filter_model)));
+        filter_model
+    }
+
+    // Sets the filter expression typed above the table, or clears it, and shows only the
+    // people it matches
+    fn set_filter(&mut self, filter: Option<Filter>) {
+        self.filter = filter;
+        self.update_matching_ids();
+        self.filter_model.refilter();
+    }
+
+    // Shows only the people whose name matches the text of the search box
+    fn set_search_text(&mut self, text: &str) {
+        self.search_text = text.trim().to_string();
+        self.update_matching_ids();
+        self.filter_model.refilter();
+    }
+
+    fn update_matching_ids(&self) {
+        if self.filter.is_none() && self.search_text.is_empty() {
+            *self.matching_ids.borrow_mut() = None;
+            return;
+        }
+        let found: Option<HashSet<usize>> =
+            (!self.search_text.is_empty()).then(|| search(&self.people, &self.search_text).into_iter().collect());
+        let as_of = today();
+        let ids = self
+            .people
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| {
+                found.as_ref().is_none_or(|found| found.contains(i))
+                    && self.filter.as_ref().is_none_or(|filter| filter.matches(p, as_of))
+            })
+            .map(|(_, p)| p.id.clone())
+            .collect();
+        *self.matching_ids.borrow_mut() = Some(ids);
+    }
+
+    // Lists every tag in use with a check button; checking tags shows only the people who
+    // have all of them
+    fn update_tag_sidebar(&self) {
+        while let Some(child) = self.tag_sidebar.first_child() {
+            self.tag_sidebar.remove(&child);
+        }
+        let counts = tag_counts(&self.people);
+        self.tag_filter.borrow_mut().retain(|t| counts.contains_key(t));
+        self.tag_sidebar.set_visible(!counts.is_empty());
+        if counts.is_empty() {
+            return;
+        }
+
+        let heading = Label::builder()
+            .label(format!("<b>{}</b>", gettext("Tags")))
+            .use_markup(true)
+            .xalign(0.0)
+            .build();
+        self.tag_sidebar.append(&heading);
+        for (tag, count) in counts {
+            let button = CheckButton::builder()
+                .label(format!("{} ({})", tag, count))
+                .active(self.tag_filter.borrow().contains(&tag))
+                .build();
+            let tag_filter = self.tag_filter.clone();
+            let filter_model = self.filter_model.clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    tag_filter.borrow_mut().insert(tag.clone());
+                } else {
+                    tag_filter.borrow_mut().remove(&tag);
+                }
+                filter_model.refilter();
+            });
+            self.tag_sidebar.append(&button);
+        }
+    }
+
+    // Loads people from `path`, skipping records that cannot be read, and locks it.
+    // Returns the owner of an existing lock, if any, and the skipped records.
+    fn load(&mut self, path: PathBuf) -> Result<(Option<String>, Vec<LoadProblem>), Box<dyn std::error::Error>> {
+        let fingerprint = FileFingerprint::of(&path).ok();
+        let store = open_store(&path, None);
+        let (people, problems) = store.load_lenient()?;
+        self.relations = store.load_relations()?;
+        self.schema = FieldSchema::load_for(&path)?;
+        self.rebuild_columns();
+        self.base_people = people.clone();
+        self.people = people;
+        self.fingerprint = fingerprint;
+        let other_owner = self.lock_file(&path);
+        self.last_file = Some(path);
+        Ok((other_owner, problems))
+    }
+
+    // Replaces the advisory lock with one on `path`
+    fn lock_file(&mut self, path: &Path) -> Option<String> {
+        self.lock = None;
+        match LockFile::acquire(path) {
+            Ok(LockStatus::Acquired(lock)) => {
+                self.lock = Some(lock);
+                None
+            }
+            Ok(LockStatus::HeldBy(owner)) => Some(owner),
+            Err(e) => {
+                log::warn!("Could not create lock file: {}", e);
+                None
+            }
+        }
+    }
+
+    // Takes the lock on the open file from another editor that is known to be closed
+    fn take_over_lock(&mut self) {
+        let Some(path) = &self.last_file else { return };
+        match LockFile::take_over(path) {
+            Ok(lock) => self.lock = Some(lock),
+            Err(e) => log::warn!("Could not create lock file: {}", e),
+        }
+    }
+
+    // Replaces the list store with one that has a column for each custom field, and
+    // shows those columns after the built-in ones
+    fn rebuild_columns(&mut self) {
+        let mut types = vec![String::static_type(); GUI_TABLE_HEADER_COLUMNS.len()];
+        types.push(u64::static_type());
+        types.extend(self.schema.fields.iter().map(|_| String::static_type()));
+        self.list_store = ListStore::new(&types);
+        self.filter_model = Self::attach_model(&self.list_store, &self.tree_view, &self.tag_filter, &self.matching_ids);
+
+        while self.tree_view.n_columns() as usize > GUI_TABLE_HEADER_COLUMNS.len() {
+            if let Some(column) = self.tree_view.column(GUI_TABLE_HEADER_COLUMNS.len() as i32) {
+                self.tree_view.remove_column(&column);
+            }
+        }
+        for (i, field) in self.schema.fields.iter().enumerate() {
+            let column = TreeViewColumn::new();
+            let cell = CellRendererText::new();
+            column.pack_start(&cell, true);
+            column.add_attribute(&cell, "text", (FIRST_CUSTOM_COLUMN + i as u32) as i32);
+            column.set_title(field.label());
+            column.set_sort_column_id((FIRST_CUSTOM_COLUMN + i as u32) as i32);
+            self.tree_view.append_column(&column);
+        }
+    }
+
+    // Records that the people in memory were just written to `path`
+    fn mark_saved(&mut self, path: PathBuf) {
+        self.base_people = self.people.clone();
+        self.fingerprint = FileFingerprint::of(&path).ok();
+        // Keep the custom field definitions with the file, including ones that came with an import
+        if !self.schema.fields.is_empty() {
+            if let Err(e) = self.schema.save_for(&path) {
+                log::warn!("Could not save custom fields: {}", e);
+            }
+        }
+        if self.last_file.as_ref() != Some(&path) {
+            self.lock_file(&path);
+            self.last_file = Some(path);
         }
     }
 
@@ -42,13 +293,15 @@ impl AppState {
     fn update_display(&self) {
         // Clear existing data
         self.list_store.clear();
+        self.update_tag_sidebar();
+        self.update_matching_ids();
         
         if self.people.is_empty() {
             // Show prompt when no file is loaded
             self.list_store.set(
                 &self.list_store.append(),
                 &[
-                    (0, &0u32),  // ID column must be u32
+                    (0, &"".to_string()),
                     (1, &format!("<span style='italic'>{}</span>", gettext("No people loaded"))),
                     (2, &"".to_string()),
                     (3, &"".to_string()),
@@ -60,19 +313,35 @@ impl AppState {
             return;
         }
         
+        // Rank people by date of birth, so sorting by age puts partial and unknown dates last
+        let mut by_birth: Vec<usize> = (0..self.people.len()).collect();
+        by_birth.sort_by_key(|&i| self.people[i].date_of_birth);
+        let mut birth_rank = vec![0u64; self.people.len()];
+        for (rank, &i) in by_birth.iter().enumerate() {
+            birth_rank[i] = rank as u64;
+        }
+
         // Add people data to the list store
-        for person in &self.people {
+        for (i, person) in self.people.iter().enumerate() {
+            let iter = self.list_store.append();
             self.list_store.set(
-                &self.list_store.append(),
+                &iter,
                 &[
                     (0, &person.id),
                     (1, &person.first_name),
                     (2, &person.last_name),
-                    (3, &person.get_age().to_string()),
-                    (4, &Self::format_sport_display(&person.favorite_sport)),
+                    (3, &person.age_display_on(today())),
+                    (4, &person.sports_summary()),
+                    (TAGS_COLUMN, &person.tags.join(", ")),
+                    (AGE_SORT_COLUMN, &birth_rank[i]),
                 ],
             );
+            for (j, field) in self.schema.fields.iter().enumerate() {
+                let value = person.custom.get(&field.name).cloned().unwrap_or_default();
+                self.list_store.set_value(&iter, FIRST_CUSTOM_COLUMN + j as u32, &value.to_value());
+            }
         }
+        self.filter_model.refilter();
         // Clear selection after loading new data
         self.tree_view.selection().unselect_all();
     }
@@ -99,8 +368,24 @@ fn show_confirm_dialog(parent: &ApplicationWindow, message: &str, on_confirm: Bo
     dialog.show();
 }
 
+// Helper to show an informational or error message
+fn show_message_dialog(parent: &ApplicationWindow, title: &str, message: &str) {
+    let dialog = Dialog::with_buttons(
+        Some(title),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[(&gettext("OK"), ResponseType::Ok)],
+    );
+    let content_area = dialog.content_area();
+    let label = Label::builder().label(message).build();
+    content_area.append(&label);
+
+    dialog.connect_response(|d, _| d.close());
+    dialog.show();
+}
+
 // Helper to show the Add/Edit dialog
-fn show_person_dialog(parent: &ApplicationWindow, person: Option<&Person>, on_save: Box<dyn Fn(Person) + 'static>) {
+fn show_person_dialog(parent: &ApplicationWindow, person: Option<&Person>, schema: &FieldSchema, on_save: Box<dyn Fn(Person) + 'static>) {
     let title = if person.is_some() { gettext("Edit Person") } else { gettext("Add Person") };
     let dialog = Dialog::with_buttons(
         Some(title.as_str()),
@@ -113,9 +398,15 @@ fn show_person_dialog(parent: &ApplicationWindow, person: Option<&Person>, on_sa
     let first_name_entry = Entry::builder().placeholder_text(&gettext("First Name")).build();
     let last_name_entry = Entry::builder().placeholder_text(&gettext("Last Name")).build();
     
-    let dob_entry = Entry::builder().placeholder_text(&gettext("Date of Birth (YYYY-MM-DD)")).build();
+    let dob_entry = Entry::builder().placeholder_text(&gettext("Date of Birth (YYYY-MM-DD, YYYY or --MM-DD)")).build();
     let sport_combo = ComboBoxText::new();
     let custom_sport_entry = Entry::builder().placeholder_text(&gettext("Custom Sport")).build();
+    let more_sports_entry = Entry::builder().placeholder_text(&gettext("Other favorite sports in order, separated by commas")).build();
+    let email_entry = Entry::builder().placeholder_text(&gettext("Email addresses, separated by commas")).build();
+    let phone_entry = Entry::builder().placeholder_text(&gettext("Phone numbers with country code, separated by commas")).build();
+    let address_entry = Entry::builder().placeholder_text(&gettext("Address")).build();
+    let tags_entry = Entry::builder().placeholder_text(&gettext("Tags, separated by commas")).build();
+    let notes_view = TextView::builder().wrap_mode(gtk::WrapMode::WordChar).build();
     
     // Populate sport dropdown with all known sports
     for sport in Sport::all_known_sports() {
@@ -126,42 +417,83 @@ fn show_person_dialog(parent: &ApplicationWindow, person: Option<&Person>, on_sa
     sport_combo.append_text(&format!("{} {}", "", gettext("Other")));
 
     // Store the original person's ID for editing
-    let original_id = person.map(|p| p.id);
+    let original_id = person.map(|p| p.id.clone());
+    // Values of custom fields, including ones no longer in the schema
+    let original_custom = person.map(|p| p.custom.clone()).unwrap_or_default();
+    let original_extra_columns = person.map(|p| p.extra_columns.clone()).unwrap_or_default();
+    let original = person.cloned();
 
     if let Some(p) = person {
         first_name_entry.set_text(&p.first_name);
         last_name_entry.set_text(&p.last_name);
         dob_entry.set_text(&p.date_of_birth.to_string());
+        email_entry.set_text(&p.emails.join(", "));
+        phone_entry.set_text(&p.phones.join(", "));
+        address_entry.set_text(&p.address);
+        tags_entry.set_text(&p.tags.join(", "));
+        notes_view.buffer().set_text(&p.notes);
         
-        // Set the sport dropdown to the current sport
-        let sport_display = format!("{} {}", p.favorite_sport.emoji(), p.favorite_sport);
-        let known_sports = Sport::all_known_sports();
-        let mut found_index = None;
-        
-        for (i, sport) in known_sports.iter().enumerate() {
-            let display_text = format!("{} {}", sport.emoji(), sport);
-            if display_text == sport_display {
-                found_index = Some(i);
-                break;
-            }
-        }
-        
-        if let Some(index) = found_index {
-            sport_combo.set_active(Some(index as u32));
-        } else {
-            // If it's a custom sport, select "Other" and populate the custom entry
-            sport_combo.set_active(Some(known_sports.len() as u32));
-            if let Sport::Other(custom_name) = &p.favorite_sport {
-                custom_sport_entry.set_text(custom_name);
+        // Set the sport dropdown to the primary sport, and list the others after it
+        if let Some(primary) = p.primary_sport() {
+            let known_sports = Sport::all_known_sports();
+            if let Some(index) = known_sports.iter().position(|sport| sport == primary) {
+                sport_combo.set_active(Some(index as u32));
+            } else {
+                // If it's a custom sport, select "Other" and populate the custom entry
+                sport_combo.set_active(Some(known_sports.len() as u32));
+                if let Sport::Other(custom_name) = primary {
+                    custom_sport_entry.set_text(custom_name);
+                }
             }
         }
+        more_sports_entry.set_text(&Sport::to_list_text(p.favorite_sports.get(1..).unwrap_or_default()));
     }
 
+    // Validation errors are shown under the field they are about
+    let error_label = || {
+        let label = Label::builder().xalign(0.0).visible(false).build();
+        label.add_css_class("error");
+        label
+    };
+    let first_name_error = error_label();
+    let last_name_error = error_label();
+    let dob_error = error_label();
+    let sport_error = error_label();
+    let email_error = error_label();
+    let phone_error = error_label();
+    let tags_error = error_label();
+
     vbox.append(&first_name_entry);
+    vbox.append(&first_name_error);
     vbox.append(&last_name_entry);
+    vbox.append(&last_name_error);
     vbox.append(&dob_entry);
+    vbox.append(&dob_error);
     vbox.append(&sport_combo);
     vbox.append(&custom_sport_entry);
+    vbox.append(&more_sports_entry);
+    vbox.append(&sport_error);
+    vbox.append(&email_entry);
+    vbox.append(&email_error);
+    vbox.append(&phone_entry);
+    vbox.append(&phone_error);
+    vbox.append(&address_entry);
+    vbox.append(&tags_entry);
+    vbox.append(&tags_error);
+    vbox.append(&Label::builder().label(&gettext("Notes")).xalign(0.0).build());
+    vbox.append(&ScrolledWindow::builder().min_content_height(60).child(&notes_view).build());
+
+    // One entry per custom field
+    let custom_entries: Vec<(FieldDefinition, Entry, Label)> = schema.fields.iter().map(|field| {
+        let entry = Entry::builder()
+            .placeholder_text(format!("{} ({})", field.label(), field.hint()))
+            .text(original_custom.get(&field.name).map(|v| v.as_str()).unwrap_or(""))
+            .build();
+        let error = error_label();
+        vbox.append(&entry);
+        vbox.append(&error);
+        (field.clone(), entry, error)
+    }).collect();
     
     // Initially hide the custom sport entry
     custom_sport_entry.set_visible(false);
@@ -178,53 +510,110 @@ fn show_person_dialog(parent: &ApplicationWindow, person: Option<&Person>, on_sa
     
     dialog.connect_response(move |d, resp| {
         if resp == ResponseType::Ok {
-            // Validate date format with regex
-            let date_text = dob_entry.text();
-            let date_regex = regex::Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
-            
-            let date_of_birth = if date_regex.is_match(&date_text) {
-                chrono::NaiveDate::parse_from_str(&date_text, "%Y-%m-%d").unwrap_or_else(|_| chrono::NaiveDate::from_ymd_opt(1900,1,1).unwrap())
-            } else {
-                chrono::NaiveDate::from_ymd_opt(1900,1,1).unwrap()
+            let mut errors: Vec<ValidationError> = Vec::new();
+            let date_of_birth = match parse_date_of_birth(&dob_entry.text()) {
+                Ok(date) => date,
+                Err(e) => {
+                    errors.push(e);
+                    birth_date::BirthDate::Unknown
+                }
             };
             
-            // Get sport from dropdown or custom entry
-            let sport = if let Some(active_index) = sport_combo.active() {
-                let known_sports = Sport::all_known_sports();
-                let total_items = known_sports.len() as u32 + 1; // +1 for "Other"
-                if active_index == total_items - 1 {
-                    // "Other" is selected, use custom sport entry
-                    Sport::from_string(&custom_sport_entry.text())
-                } else {
-                    // A known sport is selected, get it from the dropdown
-                    if let Some(sport) = known_sports.get(active_index as usize) {
-                        sport.clone()
-                    } else {
-                        Sport::Other("Unknown".to_string())
+            // Get the primary sport from dropdown or custom entry. Nothing is selected for
+            // people without a sport.
+            let mut sports: Vec<Sport> = sport_combo
+                .active()
+                .map(|active_index| {
+                    let known_sports = Sport::all_known_sports();
+                    match known_sports.get(active_index as usize) {
+                        // A known sport is selected, get it from the dropdown
+                        Some(sport) => sport.clone(),
+                        // "Other" is selected, use custom sport entry
+                        None => Sport::from_string(&custom_sport_entry.text()),
                     }
-                }
-            } else {
-                Sport::Other("Unknown".to_string())
-            };
+                })
+                .into_iter()
+                .collect();
+            // The other sports follow in the order they were typed
+            sports.extend(Sport::from_list_text(&more_sports_entry.text()));
             
-            let person = if let Some(id) = original_id {
+            let mut person = if let Some(ref id) = original_id {
                 // Editing: preserve the original ID
                 Person::with_id(
-                    id,
+                    id.clone(),
                     first_name_entry.text().to_string(),
                     last_name_entry.text().to_string(),
                     date_of_birth,
-                    sport,
+                    sports,
                 )
             } else {
-                // Adding: create new person with auto-generated ID
+                // Adding: the ID is allocated when the person is added
                 Person::new(
                     first_name_entry.text().to_string(),
                     last_name_entry.text().to_string(),
                     date_of_birth,
-                    sport,
+                    sports,
                 )
             };
+
+            // Lists may be separated by commas or semicolons
+            let split_list = |text: &str| -> Vec<String> {
+                text.split([',', ';']).map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect()
+            };
+            person.emails = split_list(&email_entry.text());
+            person.phones = split_list(&phone_entry.text());
+            person.address = address_entry.text().trim().to_string();
+            person.tags = split_list(&tags_entry.text());
+            let notes = notes_view.buffer();
+            person.notes = notes.text(&notes.start_iter(), &notes.end_iter(), false).trim().to_string();
+
+            if let Err(e) = validate_change(&person, original.as_ref()) {
+                // The date was already reported if it could not be parsed
+                let date_reported = !errors.is_empty();
+                errors.extend(e.0.into_iter().filter(|e| !(date_reported && e.field() == Field::DateOfBirth)));
+            }
+            for (field, label) in [
+                (Field::FirstName, &first_name_error),
+                (Field::LastName, &last_name_error),
+                (Field::DateOfBirth, &dob_error),
+                (Field::FavoriteSport, &sport_error),
+                (Field::Email, &email_error),
+                (Field::Phone, &phone_error),
+                (Field::Tags, &tags_error),
+            ] {
+                match errors.iter().find(|e| e.field() == field) {
+                    Some(error) => {
+                        label.set_text(&error.to_string());
+                        label.set_visible(true);
+                    }
+                    None => label.set_visible(false),
+                }
+            }
+
+            person.custom = original_custom.clone();
+            person.extra_columns = original_extra_columns.clone();
+            let mut custom_invalid = false;
+            for (field, entry, error) in &custom_entries {
+                match field.parse_value(&entry.text()) {
+                    Ok(value) => {
+                        error.set_visible(false);
+                        if value.is_empty() {
+                            person.custom.remove(&field.name);
+                        } else {
+                            person.custom.insert(field.name.clone(), value);
+                        }
+                    }
+                    Err(message) => {
+                        error.set_text(&message);
+                        error.set_visible(true);
+                        custom_invalid = true;
+                    }
+                }
+            }
+            if !errors.is_empty() || custom_invalid {
+                // Keep the dialog open so the fields can be corrected
+                return;
+            }
             on_save(person);
         }
         d.close();
@@ -246,12 +635,14 @@ fn main() {
         .flags(ApplicationFlags::HANDLES_OPEN)
         .build();
 
-    app.connect_activate(build_ui);
+    app.connect_activate(|app| {
+        build_ui(app);
+    });
     app.connect_open(open_file);
     app.run();
 }
 
-fn build_ui(app: &Application) {
+fn build_ui(app: &Application) -> (ApplicationWindow, Rc<RefCell<AppState>>) {
     use gtk::glib;
 
     // Create menu bar with buttons
@@ -260,6 +651,9 @@ fn build_ui(app: &Application) {
     // File menu buttons
     let open_btn = GtkButton::builder().label(&gettext("Open")).build();
     let save_btn = GtkButton::builder().label(&gettext("Save")).build();
+    let import_btn = GtkButton::builder().label(&gettext("Import")).build();
+    let export_btn = GtkButton::builder().label(&gettext("Export")).build();
+    let birthdays_btn = GtkButton::builder().label(&gettext("Birthdays")).build();
     let exit_btn = GtkButton::builder().label(&gettext("Exit")).build();
     
     // People menu buttons
@@ -270,6 +664,9 @@ fn build_ui(app: &Application) {
     
     menu_bar.append(&open_btn);
     menu_bar.append(&save_btn);
+    menu_bar.append(&import_btn);
+    menu_bar.append(&export_btn);
+    menu_bar.append(&birthdays_btn);
     menu_bar.append(&exit_btn);
     menu_bar.append(&add_btn);
     menu_bar.append(&edit_btn);
@@ -279,16 +676,18 @@ fn build_ui(app: &Application) {
     // Create list store with column types
     let list_store = ListStore::new(
         &[
-            u32::static_type(), 
             String::static_type(), 
             String::static_type(), 
             String::static_type(), 
             String::static_type(), 
+            String::static_type(), 
+            String::static_type(),
+            u64::static_type(),
         ]);
     
-    // Create tree view
+    // Create tree view; the app state gives it its model
     let tree_view = Rc::new(TreeView::builder()
-        .model(&list_store)
+        .hexpand(true)
         .build());
     tree_view.selection().set_mode(SelectionMode::Single);
     
@@ -298,10 +697,13 @@ fn build_ui(app: &Application) {
         let cell = CellRendererText::new();
         
         column.pack_start(&cell, true);
-        column.add_attribute(&cell, "markup", i as i32);
+        // Tags are shown as typed, so they may contain markup characters
+        column.add_attribute(&cell, if i == TAGS_COLUMN as usize { "text" } else { "markup" }, i as i32);
         
         let title = gettext(*header);
         column.set_title(title.as_str());
+        // Ages sort by date of birth rather than by their text
+        column.set_sort_column_id(if i == 3 { AGE_SORT_COLUMN as i32 } else { i as i32 });
         
         tree_view.append_column(&column);
     }
@@ -311,7 +713,50 @@ fn build_ui(app: &Application) {
         .orientation(Orientation::Vertical)
         .build();
     vbox.append(&menu_bar);
-    vbox.append(tree_view.as_ref());
+    let search_entry = SearchEntry::builder()
+        .placeholder_text(&gettext("Search by name"))
+        .build();
+    let filter_entry = Entry::builder()
+        .placeholder_text(&gettext("Filter, e.g. age > 30 and sport = tennis"))
+        .hexpand(true)
+        .build();
+    let filter_bar = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    filter_bar.append(&search_entry);
+    filter_bar.append(&filter_entry);
+    vbox.append(&filter_bar);
+    let tag_sidebar = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    let content = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .vexpand(true)
+        .build();
+    content.append(&tag_sidebar);
+    content.append(tree_view.as_ref());
+    let related_panel = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .margin_start(6)
+        .margin_end(6)
+        .visible(false)
+        .build();
+    related_panel.append(&Label::builder()
+        .label(format!("<b>{}</b>", gettext("Related People")))
+        .use_markup(true)
+        .xalign(0.0)
+        .build());
+    let related_label = Label::builder().xalign(0.0).yalign(0.0).build();
+    related_panel.append(&related_label);
+    content.append(&related_panel);
+    vbox.append(&content);
 
     let window = ApplicationWindow::builder()
         .application(app)
@@ -322,10 +767,66 @@ fn build_ui(app: &Application) {
         .build();
 
     // Create app state
-    let app_state = Rc::new(RefCell::new(AppState::new(list_store, &tree_view)));
+    let app_state = Rc::new(RefCell::new(AppState::new(list_store, &tree_view, &tag_sidebar)));
     
     // Show initial prompt
     app_state.borrow().update_display();
+
+    // Narrow the table down to matching names as the user types
+    search_entry.connect_search_changed(glib::clone!(@weak app_state => move |entry| {
+        app_state.borrow_mut().set_search_text(&entry.text());
+    }));
+
+    // Show only the people matching the filter expression; a filter that cannot be parsed is
+    // marked and explained in a tooltip, and the last valid one stays in use
+    filter_entry.connect_changed(glib::clone!(@weak app_state => move |entry| {
+        let text = entry.text();
+        let mut state = app_state.borrow_mut();
+        let parsed = if text.trim().is_empty() {
+            Ok(None)
+        } else {
+            Filter::parse(&text, &state.schema).map(Some)
+        };
+        match parsed {
+            Ok(filter) => {
+                entry.remove_css_class("error");
+                entry.set_tooltip_text(None);
+                state.set_filter(filter);
+            }
+            Err(e) => {
+                entry.add_css_class("error");
+                entry.set_tooltip_text(Some(&e));
+            }
+        }
+    }));
+
+    // Show who the selected person is related to
+    tree_view.selection().connect_changed(glib::clone!(@weak app_state, @weak related_panel, @weak related_label => move |selection| {
+        let id = selection
+            .selected()
+            .and_then(|(model, iter)| model.get::<Option<String>>(&iter, 0))
+            .unwrap_or_default();
+        // The selection also changes while the list is refilled; it ends up empty then
+        let state = match app_state.try_borrow() {
+            Ok(state) if !id.is_empty() => state,
+            _ => {
+                related_panel.set_visible(false);
+                return;
+            }
+        };
+        let related = related_people(&state.relations, &state.people, &id);
+        let text = if related.is_empty() {
+            gettext("No related people")
+        } else {
+            related
+                .iter()
+                .map(|(description, person)| format!("{} {} {}", description, person.first_name, person.last_name))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        related_label.set_text(&text);
+        related_panel.set_visible(true);
+    }));
     
     // Create action handlers
     let app_state_open = app_state.clone();
@@ -349,11 +850,9 @@ fn build_ui(app: &Application) {
 
     save_btn.connect_clicked(glib::clone!(@weak window_save, @weak app_state_save => move |_| {
         log::info!("Save button clicked");
-        let mut state = app_state_save.borrow_mut();
-        if let Some(ref file) = state.last_file {
-            if let Err(e) = Person::write_to_csv(file, &state.people) {
-                log::error!("Failed to save: {}", e);
-            }
+        let last_file = app_state_save.borrow().last_file.clone();
+        if let Some(file) = last_file {
+            save_people(&window_save, app_state_save.clone(), file);
         } else {
             // Prompt for file
             let dialog = FileChooserDialog::builder()
@@ -363,16 +862,11 @@ fn build_ui(app: &Application) {
                 .build();
             dialog.add_button("Cancel", ResponseType::Cancel);
             dialog.add_button("Save", ResponseType::Accept);
-            dialog.connect_response(glib::clone!(@weak app_state_save => move |dialog, resp| {
+            dialog.connect_response(glib::clone!(@weak window_save, @weak app_state_save => move |dialog, resp| {
                 if resp == ResponseType::Accept {
                     if let Some(file) = dialog.file() {
                         if let Some(path) = file.path() {
-                            let mut state = app_state_save.borrow_mut();
-                            if let Err(e) = Person::write_to_csv(&path, &state.people) {
-                                log::error!("Failed to save: {}", e);
-                            } else {
-                                state.last_file = Some(path);
-                            }
+                            save_people(&window_save, app_state_save.clone(), path);
                         }
                     }
                 }
@@ -382,16 +876,28 @@ fn build_ui(app: &Application) {
         }
     }));
 
+    let app_state_exit = app_state.clone();
     exit_btn.connect_clicked(move |_| {
         log::info!("Exit button clicked");
+        // Release the lock file, `exit` skips destructors
+        app_state_exit.borrow_mut().lock = None;
         std::process::exit(0);
     });
 
+    app.connect_shutdown(glib::clone!(@weak app_state => move |_| {
+        app_state.borrow_mut().lock = None;
+    }));
+
     add_btn.connect_clicked(glib::clone!(@weak window_add, @weak app_state_add => move |_| {
         log::info!("Add button clicked");
-        show_person_dialog(&window_add, None, Box::new(glib::clone!(@weak app_state_add => move |person| {
-            app_state_add.borrow_mut().people.push(person);
-            app_state_add.borrow().update_display();
+        let schema = app_state_add.borrow().schema.clone();
+        show_person_dialog(&window_add, None, &schema, Box::new(glib::clone!(@weak app_state_add => move |person| {
+            let mut state = app_state_add.borrow_mut();
+            let strategy = IdStrategy::used_by(&state.people);
+            if let Err(e) = add_person(&mut state.people, person, strategy) {
+                log::error!("Failed to add person: {}", e);
+            }
+            state.update_display();
         })));
     }));
 
@@ -399,13 +905,15 @@ fn build_ui(app: &Application) {
         log::info!("Edit button clicked");
         let state = app_state_edit.borrow();
         if let Some((model, iter)) = state.tree_view.selection().selected() {
-            let id_value: u32 = model.get::<u32>(&iter, 0);
+            let id_value: String = model.get::<String>(&iter, 0);
 
             if let Some(idx) = state.people.iter().position(|p| p.id == id_value) {
                 let person = state.people[idx].clone();
                 log::info!("Editing person with ID {}", person.id);
-                show_person_dialog(&window_edit, Some(&person), Box::new(glib::clone!(@weak app_state_edit => move |new_person| {
-                    app_state_edit.borrow_mut().people[idx] = new_person;
+                show_person_dialog(&window_edit, Some(&person), &state.schema, Box::new(glib::clone!(@weak app_state_edit => move |new_person| {
+                    if let Err(e) = edit_person(&mut app_state_edit.borrow_mut().people, idx, new_person) {
+                        log::error!("Failed to edit person: {}", e);
+                    }
                     app_state_edit.borrow().update_display();
                 })));
             } else {
@@ -420,17 +928,19 @@ fn build_ui(app: &Application) {
         log::info!("Delete button clicked");
         let state = app_state_delete.borrow();
         if let Some((model, iter)) = state.tree_view.selection().selected() {
-            let id_value: u32 = model.get::<u32>(&iter, 0);  // column 0 is ID
+            let id_value: String = model.get::<String>(&iter, 0);  // column 0 is ID
             if let Some(idx) = state.people.iter().position(|p| p.id == id_value) {
                 if let Some(person) = state.people.get(idx).cloned() {
                     let message = format!("{} {} {}?", gettext("Are you sure you want to delete"), person.first_name, person.last_name);
                     show_confirm_dialog(&window_delete, &message, Box::new(glib::clone!(@weak app_state_delete => move || {
                         let mut state = app_state_delete.borrow_mut();
                         if let Some((model, iter)) = state.tree_view.selection().selected() {
-                            let id_value: u32 = model.get::<u32>(&iter, 0);  // column 0 is ID
+                            let id_value: String = model.get::<String>(&iter, 0);  // column 0 is ID
                             if let Some(idx) = state.people.iter().position(|p| p.id == id_value) {
                                 log::info!("Deleting person with ID {}", id_value);
+                                let state = &mut *state;
                                 state.people.remove(idx);
+                                retain_valid(&mut state.relations, &state.people);
                                 state.update_display();
                             } else {
                                 log::warn!("No person found with ID {}", id_value);
@@ -453,7 +963,112 @@ fn build_ui(app: &Application) {
         show_print_dialog(&window_print, app_state_print.clone());
     }));
 
+    import_btn.connect_clicked(glib::clone!(@weak window, @weak app_state => move |_| {
+        log::info!("Import button clicked");
+        import_file_dialog(&window, app_state.clone());
+    }));
+
+    export_btn.connect_clicked(glib::clone!(@weak window, @weak app_state => move |_| {
+        log::info!("Export button clicked");
+        export_file_dialog(&window, app_state.clone());
+    }));
+
+    birthdays_btn.connect_clicked(glib::clone!(@weak window, @weak app_state => move |_| {
+        log::info!("Birthdays button clicked");
+        show_birthdays_dialog(&window, app_state.clone());
+    }));
+
     window.present();
+    (window, app_state)
+}
+
+// Saves to `path`, asking how to proceed if someone else changed the file since it was loaded
+fn save_people(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>>, path: PathBuf) {
+    let changed_on_disk = {
+        let state = app_state.borrow();
+        state.last_file.as_ref() == Some(&path)
+            && state.fingerprint.as_ref().is_some_and(|f| f.has_changed(&path))
+    };
+    if !changed_on_disk {
+        write_people(parent, &app_state, path);
+        return;
+    }
+
+    let dialog = Dialog::with_buttons(
+        Some(&gettext("File changed on disk")),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&gettext("Cancel"), ResponseType::Cancel),
+            (&gettext("Reload"), ResponseType::Other(RESPONSE_RELOAD)),
+            (&gettext("Overwrite"), ResponseType::Other(RESPONSE_OVERWRITE)),
+            (&gettext("Merge"), ResponseType::Other(RESPONSE_MERGE)),
+        ],
+    );
+    let content_area = dialog.content_area();
+    let label = Label::builder()
+        .label(&gettext("The file was changed by another program since it was opened."))
+        .build();
+    content_area.append(&label);
+
+    let parent = parent.clone();
+    dialog.connect_response(move |d, resp| {
+        d.close();
+        match resp {
+            ResponseType::Other(RESPONSE_MERGE) => match open_store(&path, None).load() {
+                Ok(theirs) => {
+                    let conflicts = {
+                        let mut state = app_state.borrow_mut();
+                        let outcome = merge_people(&state.base_people, &state.people, &theirs);
+                        state.people = outcome.people;
+                        outcome.conflicts
+                    };
+                    app_state.borrow().update_display();
+                    write_people(&parent, &app_state, path.clone());
+                    if !conflicts.is_empty() {
+                        show_message_dialog(&parent, &gettext("Merge conflicts"), &conflicts.join("\n"));
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to load people: {}", e);
+                    show_message_dialog(&parent, &gettext("Error"), &e.to_string());
+                }
+            },
+            ResponseType::Other(RESPONSE_OVERWRITE) => write_people(&parent, &app_state, path.clone()),
+            ResponseType::Other(RESPONSE_RELOAD) => {
+                let result = app_state.borrow_mut().load(path.clone());
+                match result {
+                    Ok(_) => app_state.borrow().update_display(),
+                    Err(e) => {
+                        log::error!("Failed to load people: {}", e);
+                        show_message_dialog(&parent, &gettext("Error"), &e.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+    dialog.show();
+}
+
+// Writes the people in memory to `path` and reports failures
+fn write_people(parent: &ApplicationWindow, app_state: &Rc<RefCell<AppState>>, path: PathBuf) {
+    let mut state = app_state.borrow_mut();
+    let store = open_store(&path, None);
+    // Relationships are saved too, so the ones of deleted people are dropped and Save As keeps them
+    let saved = store.save(&state.people).and_then(|_| {
+        if state.relations.is_empty() && state.last_file.as_ref() != Some(&path) {
+            Ok(())
+        } else {
+            store.save_relations(&state.relations)
+        }
+    });
+    if let Err(e) = saved {
+        log::error!("Failed to save: {}", e);
+        show_message_dialog(parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to save"), e));
+    } else {
+        state.mark_saved(path);
+    }
 }
 
 // Helper to show print dialog
@@ -505,8 +1120,8 @@ fn show_print_dialog(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>
     for person in &people {
         text.push_str(&format!("ID: {}\n", person.id));
         text.push_str(&format!("Name: {} {}\n", person.first_name, person.last_name));
-        text.push_str(&format!("Age: {}\n", person.get_age()));
-        text.push_str(&format!("Favorite Sport: {} {}\n", person.get_favorite_sport_emoji(), person.favorite_sport));
+        text.push_str(&format!("Age: {}\n", person.age_display_on(today())));
+        text.push_str(&format!("Favorite Sports: {} {}\n", person.get_favorite_sport_emoji(), person.sports_list()));
         text.push_str("\n");
     }
     
@@ -531,46 +1146,266 @@ fn open_file_dialog(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>>
     dialog.add_button("Cancel", ResponseType::Cancel);
     dialog.add_button("Open", ResponseType::Accept);
 
+    let parent = parent.clone();
     dialog.connect_response(move |dialog, response| {
         if response == ResponseType::Accept {
-            if let Some(file) = dialog.file() {
-                if let Some(file_path) = file.path() {
-                    log::info!("Opening file: {:?}", file_path);
-                    match Person::read_from_csv(&file_path) {
-                        Ok(people) => {
-                            log::info!("Loaded {} people", people.len());
-                            app_state.borrow_mut().people = people;
-                            app_state.borrow_mut().last_file = Some(file_path);
-                            app_state.borrow().update_display();
+            if let Some(file_path) = dialog.file().and_then(|file| file.path()) {
+                open_path(&parent, &app_state, file_path);
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+// Loads `file_path` leniently into the window, warning about other editors and offering to
+// save the records that could not be read
+fn open_path(parent: &ApplicationWindow, app_state: &Rc<RefCell<AppState>>, file_path: PathBuf) {
+    log::info!("Opening file: {:?}", file_path);
+    let result = app_state.borrow_mut().load(file_path.clone());
+    match result {
+        Ok((other_owner, problems)) => {
+            log::info!("Loaded {} people", app_state.borrow().people.len());
+            app_state.borrow().update_display();
+            if let Some(owner) = other_owner {
+                show_locked_dialog(parent, app_state, &owner);
+            }
+            if !problems.is_empty() {
+                show_skipped_records_dialog(parent, file_path, problems);
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to load people: {}", e);
+            show_message_dialog(parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to open"), e));
+        }
+    }
+}
+
+// Warns that another editor has the file open, and offers to take over its lock in case that
+// editor was closed without removing it
+fn show_locked_dialog(parent: &ApplicationWindow, app_state: &Rc<RefCell<AppState>>, owner: &str) {
+    let dialog = Dialog::with_buttons(
+        Some(&gettext("Warning")),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&gettext("Take Over Lock"), ResponseType::Other(RESPONSE_TAKE_OVER_LOCK)),
+            (&gettext("OK"), ResponseType::Ok),
+        ],
+    );
+    let content_area = dialog.content_area();
+    content_area.append(&Label::builder()
+        .label(&format!("{}: {}", gettext("This file is already open in another editor"), owner))
+        .build());
+    content_area.append(&Label::builder()
+        .label(&gettext("Only take over the lock if that editor is closed."))
+        .build());
+
+    let app_state = app_state.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Other(RESPONSE_TAKE_OVER_LOCK) {
+            app_state.borrow_mut().take_over_lock();
+        }
+        dialog.close();
+    });
+    dialog.show();
+}
+
+// Lists the records of `path` that could not be loaded, and offers to save them to
+// another file before the next save drops them
+fn show_skipped_records_dialog(parent: &ApplicationWindow, path: PathBuf, problems: Vec<LoadProblem>) {
+    let dialog = Dialog::with_buttons(
+        Some(&gettext("Some records were skipped")),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&gettext("Save Skipped Records..."), ResponseType::Other(RESPONSE_QUARANTINE)),
+            (&gettext("OK"), ResponseType::Ok),
+        ],
+    );
+    let content_area = dialog.content_area();
+    content_area.append(&Label::builder()
+        .label(&gettext("These records could not be read and will be removed from the file when it is saved:"))
+        .build());
+    let text_view = TextView::builder().editable(false).monospace(true).build();
+    let lines: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+    text_view.buffer().set_text(&lines.join("\n"));
+    let scrolled = ScrolledWindow::builder()
+        .min_content_height(150)
+        .min_content_width(500)
+        .child(&text_view)
+        .build();
+    content_area.append(&scrolled);
+
+    let parent = parent.clone();
+    dialog.connect_response(move |d, resp| {
+        if resp == ResponseType::Other(RESPONSE_QUARANTINE) {
+            let chooser = FileChooserDialog::builder()
+                .title(&gettext("Save Skipped Records"))
+                .transient_for(&parent)
+                .action(FileChooserAction::Save)
+                .build();
+            chooser.add_button(&gettext("Cancel"), ResponseType::Cancel);
+            chooser.add_button(&gettext("Save"), ResponseType::Accept);
+            let parent = parent.clone();
+            let path = path.clone();
+            let problems = problems.clone();
+            chooser.connect_response(move |chooser, resp| {
+                if resp == ResponseType::Accept {
+                    if let Some(destination) = chooser.file().and_then(|f| f.path()) {
+                        if let Err(e) = open_store(&path, None).quarantine(&problems, &destination) {
+                            log::error!("Failed to save skipped records: {}", e);
+                            show_message_dialog(&parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to save"), e));
                         }
-                        Err(e) => {
-                            log::error!("Failed to load people: {}", e);
-                            std::process::exit(1);
+                    }
+                }
+                chooser.close();
+            });
+            chooser.show();
+        }
+        d.close();
+    });
+    dialog.show();
+}
+
+// Adds the people from another file (CSV, JSON, NDJSON or SQLite) to the ones in memory
+fn import_file_dialog(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>>) {
+    let dialog = FileChooserDialog::builder()
+        .title(&gettext("Import People"))
+        .transient_for(parent)
+        .action(FileChooserAction::Open)
+        .build();
+    dialog.add_button(&gettext("Cancel"), ResponseType::Cancel);
+    dialog.add_button(&gettext("Import"), ResponseType::Accept);
+
+    let parent = parent.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                log::info!("Importing file: {:?}", path);
+                let result = {
+                    let mut state = app_state.borrow_mut();
+                    let state = &mut *state;
+                    import_from(&path, None, &mut state.people, &mut state.relations, &mut state.schema)
+                };
+                match result {
+                    Ok(outcome) => {
+                        log::info!("Imported {} people, updated {}", outcome.added, outcome.updated);
+                        if outcome.new_fields {
+                            app_state.borrow_mut().rebuild_columns();
+                        }
+                        app_state.borrow().update_display();
+                        if !outcome.problems.is_empty() {
+                            let lines: Vec<String> = outcome.problems.iter().map(|p| p.to_string()).collect();
+                            show_message_dialog(&parent, &gettext("Some records were skipped"), &lines.join("\n"));
                         }
                     }
+                    Err(e) => {
+                        log::error!("Failed to import: {}", e);
+                        show_message_dialog(&parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to import"), e));
+                    }
                 }
             }
         }
         dialog.close();
     });
-
     dialog.show();
 }
 
-fn open_file(_app: &Application, files: &[gtk::gio::File], _hint: &str) {
-    if let Some(file) = files.first() {
-        if let Some(file_path) = gtk::gio::prelude::FileExt::path(file) {
-            log::info!("Opening file: {:?}", file_path);
-            match Person::read_from_csv(&file_path) {
-                Ok(people) => {
-                    log::info!("Loaded {} people", people.len());
-                }
-                Err(e) => {
-                    log::error!("Failed to load people: {}", e);
-                    std::process::exit(1);
+// Writes the people in memory to another file; the format follows the file extension
+fn export_file_dialog(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>>) {
+    let dialog = FileChooserDialog::builder()
+        .title(&gettext("Export People (.csv, .json, .ndjson, .sqlite, .vcf)"))
+        .transient_for(parent)
+        .action(FileChooserAction::Save)
+        .build();
+    dialog.add_button(&gettext("Cancel"), ResponseType::Cancel);
+    dialog.add_button(&gettext("Export"), ResponseType::Accept);
+
+    let parent = parent.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                log::info!("Exporting to file: {:?}", path);
+                let state = app_state.borrow();
+                if let Err(e) = export_to(&path, None, &state.people, &state.relations, &state.schema) {
+                    log::error!("Failed to export: {}", e);
+                    show_message_dialog(&parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to export"), e));
                 }
             }
         }
+        dialog.close();
+    });
+    dialog.show();
+}
+
+// Asks which sport to include, then where to write the birthday calendar
+fn show_birthdays_dialog(parent: &ApplicationWindow, app_state: Rc<RefCell<AppState>>) {
+    let dialog = Dialog::with_buttons(
+        Some(&gettext("Export Birthdays")),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[(&gettext("Cancel"), ResponseType::Cancel), (&gettext("OK"), ResponseType::Ok)],
+    );
+    let content_area = dialog.content_area();
+    let sport_combo = ComboBoxText::new();
+    sport_combo.append_text(&gettext("All sports"));
+    // Offer the sports that occur in the database
+    let mut sports: Vec<Sport> = Vec::new();
+    for person in &app_state.borrow().people {
+        for sport in &person.favorite_sports {
+            if !sports.contains(sport) {
+                sports.push(sport.clone());
+            }
+        }
+    }
+    for sport in &sports {
+        sport_combo.append_text(&AppState::format_sport_display(sport));
+    }
+    sport_combo.set_active(Some(0));
+    content_area.append(&Label::builder().label(&gettext("Include people who like")).build());
+    content_area.append(&sport_combo);
+
+    let parent = parent.clone();
+    dialog.connect_response(move |d, resp| {
+        if resp == ResponseType::Ok {
+            let selected: Vec<Sport> = match sport_combo.active() {
+                Some(i) if i > 0 => sports.get(i as usize - 1).cloned().into_iter().collect(),
+                _ => Vec::new(),
+            };
+            let chooser = FileChooserDialog::builder()
+                .title(&gettext("Save Birthday Calendar (.ics)"))
+                .transient_for(&parent)
+                .action(FileChooserAction::Save)
+                .build();
+            chooser.add_button(&gettext("Cancel"), ResponseType::Cancel);
+            chooser.add_button(&gettext("Save"), ResponseType::Accept);
+            let parent = parent.clone();
+            let app_state = app_state.clone();
+            chooser.connect_response(move |chooser, resp| {
+                if resp == ResponseType::Accept {
+                    if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                        if let Err(e) = ical::write_birthday_calendar(&path, &app_state.borrow().people, &selected) {
+                            log::error!("Failed to export birthdays: {}", e);
+                            show_message_dialog(&parent, &gettext("Error"), &format!("{}: {}", gettext("Failed to export"), e));
+                        }
+                    }
+                }
+                chooser.close();
+            });
+            chooser.show();
+        }
+        d.close();
+    });
+    dialog.show();
+}
+
+// Opens the file given on the command line, e.g. `people-db-gtk people.csv`, in a new window
+fn open_file(app: &Application, files: &[gtk::gio::File], _hint: &str) {
+    let (window, app_state) = build_ui(app);
+    if let Some(file_path) = files.first().and_then(gtk::gio::prelude::FileExt::path) {
+        open_path(&window, &app_state, file_path);
     }
 }
 
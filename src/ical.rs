@@ -0,0 +1,182 @@
+use crate::atomic_file::write_atomically;
+use crate::birth_date::BirthDate;
+use crate::constants::{Sport, APP_ID};
+use crate::person::Person;
+use crate::vcard_store::{escape, fold};
+use chrono::{Datelike, NaiveDate, Utc};
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+/// Builds an iCalendar feed with a yearly, all-day birthday event for each person.
+///
+/// Event UIDs are derived from person IDs, so calendar apps subscribed to the feed
+/// update existing events instead of duplicating them. When `sports` is not empty,
+/// only people with one of those among their favorite sports are included.
+pub fn birthday_calendar(people: &[Person], sports: &[Sport]) -> String {
+    let dtstamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//arickp//People DB//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Birthdays".to_string(),
+    ];
+
+    for person in people {
+        if !sports.is_empty() && !person.favorite_sports.iter().any(|s| sports.contains(s)) {
+            continue;
+        }
+        // Birthdays without a year start in 2000, a leap year, so 29 February exists
+        let dob = match person.date_of_birth {
+            BirthDate::Full(date) => date,
+            BirthDate::MonthDay { month, day } => match NaiveDate::from_ymd_opt(2000, month, day) {
+                Some(date) => date,
+                None => continue,
+            },
+            BirthDate::Year(_) | BirthDate::Unknown => continue,
+        };
+        let name = format!("{} {}", person.first_name, person.last_name);
+        // Leap-day birthdays fall on the last day of February in other years
+        let rrule = if dob.month() == 2 && dob.day() == 29 {
+            "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        } else {
+            "RRULE:FREQ=YEARLY"
+        };
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:birthday-{}@{}", escape(&person.id), APP_ID));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", dob.format("%Y%m%d")));
+        lines.push(format!(
+            "DTEND;VALUE=DATE:{}",
+            dob.succ_opt().unwrap_or(dob).format("%Y%m%d")
+        ));
+        lines.push(rrule.to_string());
+        lines.push(format!("SUMMARY:{}", escape(&format!("Birthday: {}", name.trim()))));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            escape(&format!("Favorite sports: {}", person.sports_list()))
+        ));
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect::<Vec<_>>().join("")
+}
+
+/// Writes the birthday feed for `people` to an .ics file.
+pub fn write_birthday_calendar<P: AsRef<Path>>(
+    path: P,
+    people: &[Person],
+    sports: &[Sport],
+) -> Result<(), Box<dyn Error>> {
+    let calendar = birthday_calendar(people, sports);
+    write_atomically(&path, |file| {
+        file.write_all(calendar.as_bytes())?;
+        Ok(())
+    })?;
+    log::info!("Wrote birthday calendar to {}", path.as_ref().display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(id: &str, date_of_birth: BirthDate, sports: Vec<Sport>) -> Person {
+        Person::with_id(id.to_string(), "Ann".to_string(), "Miller".to_string(), date_of_birth, sports)
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> BirthDate {
+        BirthDate::Full(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    // The content lines of each event, unfolded
+    fn events(calendar: &str) -> Vec<Vec<String>> {
+        let unfolded = calendar.replace("\r\n ", "");
+        let mut events = Vec::new();
+        for line in unfolded.split("\r\n") {
+            match line {
+                "BEGIN:VEVENT" => events.push(Vec::new()),
+                _ => {
+                    if let Some(event) = events.last_mut() {
+                        event.push(line.to_string());
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    fn property<'a>(event: &'a [String], name: &str) -> Option<&'a str> {
+        event.iter().find_map(|l| l.strip_prefix(name)).and_then(|l| l.strip_prefix(':'))
+    }
+
+    #[test]
+    fn leap_day_birthdays_fall_on_the_last_day_of_february() {
+        let people = [person("0", date(2004, 2, 29), Vec::new()), person("1", date(2004, 3, 1), Vec::new())];
+        let events = events(&birthday_calendar(&people, &[]));
+        assert_eq!(property(&events[0], "DTSTART;VALUE=DATE"), Some("20040229"));
+        assert_eq!(property(&events[0], "DTEND;VALUE=DATE"), Some("20040301"));
+        assert_eq!(property(&events[0], "RRULE"), Some("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"));
+        assert_eq!(property(&events[1], "RRULE"), Some("FREQ=YEARLY"));
+        assert_eq!(property(&events[1], "UID"), Some("birthday-1@com.github.arickp.rustpeopledb"));
+    }
+
+    #[test]
+    fn birthdays_without_a_year_start_in_2000() {
+        let people = [
+            person("0", BirthDate::MonthDay { month: 6, day: 15 }, Vec::new()),
+            person("1", BirthDate::MonthDay { month: 2, day: 29 }, Vec::new()),
+            person("2", BirthDate::Year(1990), Vec::new()),
+            person("3", BirthDate::Unknown, Vec::new()),
+        ];
+        let events = events(&birthday_calendar(&people, &[]));
+        assert_eq!(events.len(), 2);
+        assert_eq!(property(&events[0], "DTSTART;VALUE=DATE"), Some("20000615"));
+        assert_eq!(property(&events[0], "RRULE"), Some("FREQ=YEARLY"));
+        assert_eq!(property(&events[1], "DTSTART;VALUE=DATE"), Some("20000229"));
+        assert_eq!(property(&events[1], "RRULE"), Some("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"));
+    }
+
+    #[test]
+    fn only_people_with_one_of_the_sports_are_included() {
+        let people = [
+            person("0", date(1990, 1, 1), vec![Sport::Soccer]),
+            person("1", date(1990, 1, 2), vec![Sport::Golf, Sport::Tennis]),
+            person("2", date(1990, 1, 3), Vec::new()),
+        ];
+        let uids = |sports: &[Sport]| -> Vec<String> {
+            events(&birthday_calendar(&people, sports))
+                .iter()
+                .filter_map(|e| property(e, "UID").map(|uid| uid.split('@').next().unwrap().to_string()))
+                .collect()
+        };
+        assert_eq!(uids(&[]), ["birthday-0", "birthday-1", "birthday-2"]);
+        assert_eq!(uids(&[Sport::Tennis]), ["birthday-1"]);
+        assert_eq!(uids(&[Sport::Soccer, Sport::Golf]), ["birthday-0", "birthday-1"]);
+        assert!(uids(&[Sport::Rowing]).is_empty());
+    }
+
+    #[test]
+    fn long_lines_are_folded_and_text_is_escaped() {
+        let sports = vec![Sport::Other("Kabaddi".to_string()), Sport::Other("Tug of war".to_string())];
+        let mut ann = person("0", date(1990, 5, 4), sports);
+        ann.last_name = "Miller; née Søndergaard-Østergaard, of the Østergaard-Søndergaard family".to_string();
+        let calendar = birthday_calendar(&[ann], &[]);
+
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains("\r\n "), "nothing was folded");
+        for line in calendar.split("\r\n") {
+            assert!(line.len() <= 75, "{:?} is {} octets", line, line.len());
+        }
+        let events = events(&calendar);
+        assert_eq!(
+            property(&events[0], "SUMMARY"),
+            Some("Birthday: Ann Miller\\; née Søndergaard-Østergaard\\, of the Østergaard-Søndergaard family")
+        );
+        assert_eq!(property(&events[0], "DESCRIPTION"), Some("Favorite sports: Kabaddi\\, Tug of war"));
+    }
+}
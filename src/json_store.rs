@@ -0,0 +1,174 @@
+use crate::atomic_file::write_atomically;
+use crate::person::{duplicate_ids, fill_missing_ids, Person};
+use crate::store::{LoadProblem, PeopleStore};
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The people database as JSON: either one array of people, or one person per line (NDJSON).
+///
+/// Uses the same field names and values as the CSV file, with dates in ISO 8601 format.
+pub struct JsonStore {
+    path: PathBuf,
+    lines: bool,
+}
+
+impl JsonStore {
+    /// A store holding a single JSON array.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        JsonStore {
+            path: path.as_ref().to_path_buf(),
+            lines: false,
+        }
+    }
+
+    /// A store holding newline-delimited JSON, one person per line.
+    pub fn new_lines<P: AsRef<Path>>(path: P) -> Self {
+        JsonStore {
+            path: path.as_ref().to_path_buf(),
+            lines: true,
+        }
+    }
+
+    // Reads the people as they are in the file. Like the CSV file, two people with the same
+    // ID are an error.
+    fn read(&self) -> Result<Vec<Person>, Box<dyn Error>> {
+        let content = fs::read_to_string(&self.path)?;
+        let people: Vec<Person> = if self.lines {
+            let mut people = Vec::new();
+            for (i, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let person = serde_json::from_str(line)
+                    .map_err(|e| format!("{} line {}: {}", self.path.display(), i + 1, e))?;
+                people.push(person);
+            }
+            people
+        } else {
+            serde_json::from_str(&content)
+                .map_err(|e| format!("{}: {}", self.path.display(), e))?
+        };
+        if let Some(&i) = duplicate_ids(&people).first() {
+            return Err(format!("{}: duplicate ID '{}'", self.path.display(), people[i].id).into());
+        }
+
+        log::info!("Read {} {} from JSON file: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok(people)
+    }
+}
+
+impl PeopleStore for JsonStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn create(&self) -> Result<(), Box<dyn Error>> {
+        self.save(&[])
+    }
+
+    /// Loads all people. People without an ID get a new sequential one, as in a CSV file.
+    fn load(&self) -> Result<Vec<Person>, Box<dyn Error>> {
+        let mut people = self.read()?;
+        fill_missing_ids(&mut people);
+        Ok(people)
+    }
+
+    fn load_for_import(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        Ok((self.read()?, Vec::new()))
+    }
+
+    fn save(&self, people: &[Person]) -> Result<(), Box<dyn Error>> {
+        write_atomically(&self.path, |file| {
+            if self.lines {
+                for person in people {
+                    serde_json::to_writer(&mut *file, person)?;
+                    file.write_all(b"\n")?;
+                }
+            } else {
+                serde_json::to_writer_pretty(&mut *file, people)?;
+                file.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+
+        log::info!("Wrote {} {} to JSON file: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `content` to a file of its own and returns its path
+    fn file(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("json-store-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn ids(people: &[Person]) -> Vec<&str> {
+        people.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    const ANN: &str = r#"{"id":"4","first_name":"Ann","last_name":"Miller","date_of_birth":"1990-05-06","favorite_sports":["tennis"]}"#;
+    const BOB: &str = r#"{"first_name":"Bob","last_name":"Smith","date_of_birth":"","favorite_sports":[]}"#;
+
+    #[test]
+    fn reads_arrays_and_lines() {
+        let path = file("array.json", &format!("[\n  {},\n  {}\n]\n", ANN, BOB));
+        let people = JsonStore::new(&path).load().unwrap();
+        assert_eq!(people.len(), 2);
+        assert_eq!(people[0].first_name, "Ann");
+        assert_eq!(people[1].first_name, "Bob");
+        fs::remove_file(&path).unwrap();
+
+        // Blank lines are skipped, and errors give the line
+        let path = file("lines.ndjson", &format!("{}\n\n{}\n", ANN, BOB));
+        assert_eq!(JsonStore::new_lines(&path).load().unwrap(), people);
+        fs::write(&path, format!("{}\n\n{{\"first_name\":\n", ANN)).unwrap();
+        let error = JsonStore::new_lines(&path).load().unwrap_err().to_string();
+        assert!(error.contains("line 3"), "{}", error);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_ids_are_filled_on_load_but_not_for_import() {
+        let path = file("missing.ndjson", &format!("{}\n{}\n", BOB, ANN));
+        let store = JsonStore::new_lines(&path);
+        assert_eq!(ids(&store.load().unwrap()), ["5", "4"]);
+        assert_eq!(ids(&store.load_for_import().unwrap().0), ["", "4"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let path = file("duplicates.json", &format!("[{}, {}, {}, {}]", BOB, ANN, BOB, ANN));
+        let error = JsonStore::new(&path).load().unwrap_err().to_string();
+        assert!(error.ends_with("duplicate ID '4'"), "{}", error);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saved_people_load_back() {
+        let path = file("saved.json", &format!("[{}, {}]", ANN, BOB));
+        let people = JsonStore::new(&path).load().unwrap();
+        for store in [JsonStore::new(&path), JsonStore::new_lines(&path)] {
+            store.save(&people).unwrap();
+            assert_eq!(store.load().unwrap(), people);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,56 +1,429 @@
+mod atomic_file;
+mod birth_date;
 mod constants;
+mod custom_fields;
+mod duplicates;
+mod file_guard;
+mod filter;
+mod ical;
+mod json_store;
+mod migrate;
 mod person;
+mod phonetic;
+mod relations;
+mod search;
+mod sport_catalog;
+mod sqlite_store;
+mod store;
+mod text;
+mod validation;
+mod vcard_store;
 
+use crate::birth_date::BirthDate;
 use crate::constants::Sport;
-use crate::person::{add_person, delete_person, edit_person, print_people, Person, create_new_csv_file};
+use crate::custom_fields::{FieldDefinition, FieldSchema, FieldType};
+use crate::duplicates::find_duplicates;
+use crate::file_guard::{FileFingerprint, LockFile, LockStatus};
+use crate::filter::Filter;
+use crate::person::{add_person, delete_person, edit_person, find_person_by_id, merge_people, print_people, print_people_at, tag_counts, today, IdStrategy, Person};
+use crate::relations::{add_relation, related_people, remove_relations_between, retain_valid, Relation, RelationKind};
+use crate::search::{search, search_sounds_like};
+use crate::sport_catalog::{catalog, current_language, prefer_language, SportCatalog, SportEntry};
+use crate::store::{export_to, import_from, open_store, PeopleStore, StoreFormat};
+use crate::validation::{parse_date_of_birth, parse_email, parse_phone, parse_sport, parse_tag, validate_name, Field, ValidationError, ValidationErrors};
 use chrono::NaiveDate;
-use clap::{Parser, Subcommand};
-use inquire::{Select, Text};
+use clap::{Args, Parser, Subcommand};
+use inquire::{Confirm, MultiSelect, Select, Text};
 use rustyline::{history::FileHistory, Editor, Config, Helper};
 use rustyline::completion::FilenameCompleter;
 use rustyline::hint::HistoryHinter;
 use rustyline::highlight::MatchingBracketHighlighter;
 use rustyline::validate::MatchingBracketValidator;
 use std::io::{self, Write};
+use std::path::Path;
 use env_logger;
 
 #[derive(Parser)]
 #[clap(author, version, about)]
 pub struct Cli {
-    /// The path to the CSV file containing the database
+    /// The path to the file containing the database
     file: String,
 
+    /// Storage format of the database (default: guessed from the file extension)
+    #[arg(long, value_enum)]
+    format: Option<StoreFormat>,
+
+    /// How IDs are allocated for new people (default: the kind the database already uses)
+    #[arg(long, value_enum)]
+    id_strategy: Option<IdStrategy>,
+
+    /// Skip records that cannot be read instead of refusing to open the database
+    #[arg(long)]
+    lenient: bool,
+
+    /// Save records that cannot be read to this CSV file (implies --lenient)
+    #[arg(long, value_name = "FILE")]
+    quarantine: Option<String>,
+
     #[clap(subcommand)]
     command: Option<Commands>,
 }
 
+// Contact details for `new` and `edit`. A doc comment here would become the description of
+// both commands.
+#[derive(Args)]
+pub struct ContactArgs {
+    /// Email address (can be repeated; replaces the current ones, an empty value clears them)
+    #[arg(long = "email", value_name = "ADDRESS")]
+    emails: Vec<String>,
+    /// Phone number with its country code, e.g. "+1 555 123 4567" (can be repeated; replaces
+    /// the current ones, an empty value clears them)
+    #[arg(long = "phone", value_name = "NUMBER", allow_hyphen_values = true)]
+    phones: Vec<String>,
+    /// Postal address
+    #[arg(long)]
+    address: Option<String>,
+    #[arg(long)]
+    notes: Option<String>,
+}
+
+impl ContactArgs {
+    /// Sets the given details on `person`. Returns the invalid emails and phone numbers.
+    fn apply(self, person: &mut Person) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if !self.emails.is_empty() {
+            person.emails = parse_list(&self.emails, parse_email, &mut errors);
+        }
+        if !self.phones.is_empty() {
+            person.phones = parse_list(&self.phones, parse_phone, &mut errors);
+        }
+        if let Some(address) = self.address {
+            person.address = address.trim().to_string();
+        }
+        if let Some(notes) = self.notes {
+            person.notes = notes.trim().to_string();
+        }
+        errors
+    }
+}
+
+// Parses each non-blank value, adding the failures to `errors`
+fn parse_list<T>(
+    values: &[String],
+    parse: fn(&str) -> Result<T, ValidationError>,
+    errors: &mut Vec<ValidationError>,
+) -> Vec<T> {
+    values
+        .iter()
+        .filter(|v| !v.trim().is_empty())
+        .filter_map(|v| parse(v).map_err(|e| errors.push(e)).ok())
+        .collect()
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
-    Print,
+    Print {
+        /// Show ages on this date instead of today, e.g. an eligibility cutoff date
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        as_of: Option<String>,
+        /// Only show people with this tag (can be repeated to require several)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only show people matching a filter, e.g. 'age > 30 and (sport = tennis or sport = golf)'
+        #[arg(long = "where", value_name = "FILTER")]
+        filter: Option<String>,
+    },
+    /// Find people by name, ignoring case and accents and allowing for typos
+    Search {
+        #[arg(required = true)]
+        text: Vec<String>,
+        /// Find names that sound like the text, e.g. Jon Smyth for John Smith
+        #[arg(long)]
+        sounds_like: bool,
+    },
+    /// List people who may have been entered twice
+    Duplicates,
     Delete {
-        index: usize,
+        #[arg(required_unless_present = "id")]
+        index: Option<usize>,
+        /// Select the person by ID instead of by index
+        #[arg(long, conflicts_with = "index")]
+        id: Option<String>,
     },
+    /// Change the details of a person; details that are not given stay as they are
     Edit {
-        index: usize,
+        #[arg(required_unless_present = "id")]
+        index: Option<usize>,
+        /// Select the person by ID instead of by index
+        #[arg(long, conflicts_with = "index")]
+        id: Option<String>,
         #[arg(long)]
         first_name: Option<String>,
         #[arg(long)]
         last_name: Option<String>,
-        #[arg(long)]
+        /// YYYY-MM-DD, YYYY, --MM-DD, or an empty value if unknown
+        #[arg(long, allow_hyphen_values = true)]
         date_of_birth: Option<String>,
-        #[arg(long)]
-        favorite_sport: Option<String>,
+        /// Favorite sport, repeated for each one with the primary sport first; replaces all of them
+        #[arg(long = "favorite-sport", value_name = "SPORT")]
+        favorite_sports: Vec<String>,
+        #[command(flatten)]
+        contact: ContactArgs,
+        /// Set a custom field, e.g. --field jersey_size=M (can be repeated; an empty value clears it)
+        #[arg(long = "field", value_name = "NAME=VALUE")]
+        fields: Vec<String>,
     },
+    /// Add a person
     New {
         #[arg(long)]
         first_name: Option<String>,
         #[arg(long)]
         last_name: Option<String>,
-        #[arg(long)]
+        /// YYYY-MM-DD, YYYY or --MM-DD; leave out if unknown
+        #[arg(long, allow_hyphen_values = true)]
         date_of_birth: Option<String>,
+        /// Favorite sport, repeated for each one with the primary sport first
+        #[arg(long = "favorite-sport", value_name = "SPORT")]
+        favorite_sports: Vec<String>,
+        #[command(flatten)]
+        contact: ContactArgs,
+        /// Set a custom field, e.g. --field jersey_size=M (can be repeated; an empty value clears it)
+        #[arg(long = "field", value_name = "NAME=VALUE")]
+        fields: Vec<String>,
+    },
+    /// Add the people from another database file, replacing people with the same ID
+    Import {
+        source: String,
+        /// Format of the source file (default: guessed from its extension)
+        #[arg(long, value_enum)]
+        format: Option<StoreFormat>,
+    },
+    /// Write all people to another database file
+    Export {
+        destination: String,
+        /// Format of the destination file (default: guessed from its extension)
+        #[arg(long, value_enum)]
+        format: Option<StoreFormat>,
+    },
+    /// Upgrade the database file to the current schema version
+    Migrate {
+        /// Only list the migrations that would be applied
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write an iCalendar (.ics) feed with everyone's birthday
+    Birthdays {
+        destination: String,
+        /// Only include people with this among their favorite sports (can be repeated)
+        #[arg(long = "sport")]
+        sports: Vec<String>,
+    },
+    /// Manage the custom fields stored for each person
+    Fields {
+        #[clap(subcommand)]
+        command: FieldsCommand,
+    },
+    /// Add or remove tags such as "coach" or "u12"
+    Tag {
+        #[clap(subcommand)]
+        command: TagCommand,
+    },
+    /// Show how a person is related to others
+    Relations { id: String },
+    /// Record that one person is related to another, e.g. `relate 4 parent 7`
+    Relate {
+        from_id: String,
+        /// parent, guardian, sibling, spouse, or any other name for a custom relationship
+        kind: String,
+        to_id: String,
+    },
+    /// Remove every relationship between two people
+    Unrelate { first_id: String, second_id: String },
+    /// Fix favorite sports that older versions saved under their translated names
+    RepairSports {
+        /// Language of the translated names, e.g. es or en_GB (default: the current locale)
+        #[arg(long)]
+        language: Option<String>,
+        /// Only list the sports that would be repaired
         #[arg(long)]
-        favorite_sport: Option<String>,
+        dry_run: bool,
     },
+    /// List the sports that can be chosen, or add your own
+    Sports {
+        #[clap(subcommand)]
+        command: SportsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SportsCommand {
+    /// List the sports in the catalog
+    List,
+    /// Add a sport to your own catalog, in the configuration directory
+    Add {
+        /// Key of the sport: lower case letters, digits and underscores
+        key: String,
+        /// Name shown for the sport
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        emoji: Option<String>,
+        /// Another name the sport is known by (can be repeated)
+        #[arg(long = "alias")]
+        aliases: Vec<String>,
+        /// Kind of sport, e.g. team, racket or winter
+        #[arg(long)]
+        category: Option<String>,
+        /// Name in another language, e.g. --translation es=Pickleball (can be repeated)
+        #[arg(long = "translation", value_name = "LANG=NAME")]
+        translations: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum FieldsCommand {
+    /// List the custom fields
+    List,
+    /// Add a custom field
+    Add {
+        /// Field name: lower case letters, digits and underscores
+        name: String,
+        #[arg(long = "type", value_enum, default_value_t = FieldTypeArg::String)]
+        field_type: FieldTypeArg,
+        /// An allowed value of an enum field (repeat for each option)
+        #[arg(long = "option", required_if_eq("field_type", "enum"))]
+        options: Vec<String>,
+        /// Name shown in prompts and tables instead of the field name
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Remove a custom field and its values
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum TagCommand {
+    /// List the tags in use and how many people have each
+    List,
+    /// Add tags to the selected people
+    Add {
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[command(flatten)]
+        selection: Selection,
+    },
+    /// Remove tags from the selected people
+    Remove {
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[command(flatten)]
+        selection: Selection,
+    },
+}
+
+/// Which people a bulk change applies to. A person matching any of the options is selected.
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+pub struct Selection {
+    /// Select the person with this ID (can be repeated)
+    #[arg(long = "id")]
+    ids: Vec<String>,
+    /// Select everyone who has this tag (can be repeated)
+    #[arg(long = "tagged")]
+    tagged: Vec<String>,
+    /// Select everyone with this among their favorite sports (can be repeated)
+    #[arg(long = "sport")]
+    sports: Vec<String>,
+    /// Select everyone matching a filter, e.g. 'age < 12 and tag = u12' (can be repeated)
+    #[arg(long = "where", value_name = "FILTER")]
+    filters: Vec<String>,
+    /// Select everyone
+    #[arg(long)]
+    all: bool,
+}
+
+impl Selection {
+    /// Indices of the selected people. Fails if an ID is not in use or nobody is selected.
+    fn resolve(&self, people: &[Person], schema: &FieldSchema) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+        for id in &self.ids {
+            find_person_by_id(people, id).ok_or_else(|| format!("No person with ID {}", id))?;
+        }
+        let tagged = parse_tags(&self.tagged)?;
+        let sports = parse_sports(&self.sports)?;
+        let filters = self
+            .filters
+            .iter()
+            .map(|f| Filter::parse(f, schema))
+            .collect::<Result<Vec<_>, _>>()?;
+        let selected: Vec<usize> = people
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| {
+                self.all
+                    || self.ids.contains(&p.id)
+                    || tagged.iter().any(|t| p.has_tag(t))
+                    || p.favorite_sports.iter().any(|s| sports.contains(s))
+                    || filters.iter().any(|f| f.matches(p, today()))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if selected.is_empty() {
+            return Err("No people match the selection".into());
+        }
+        Ok(selected)
+    }
+}
+
+// Applies `change` with each tag to the selected people. Returns how many people changed.
+fn change_tags(
+    people: &mut [Person],
+    selection: &Selection,
+    schema: &FieldSchema,
+    tags: &[String],
+    change: fn(&mut Person, &str) -> bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut changed = 0;
+    for index in selection.resolve(people, schema)? {
+        let mut person_changed = false;
+        for tag in tags {
+            person_changed |= change(&mut people[index], tag);
+        }
+        if person_changed {
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+// Parses tags given on the command line, reporting all invalid ones together
+fn parse_tags(tags: &[String]) -> Result<Vec<String>, ValidationErrors> {
+    let mut errors = Vec::new();
+    let tags = parse_list(tags, parse_tag, &mut errors);
+    if errors.is_empty() {
+        Ok(tags)
+    } else {
+        Err(ValidationErrors(errors))
+    }
+}
+
+// Parses sports given on the command line, suggesting the intended sport for typos
+fn parse_sports(sports: &[String]) -> Result<Vec<Sport>, ValidationErrors> {
+    let mut errors = Vec::new();
+    let sports = parse_list(sports, parse_sport, &mut errors);
+    if errors.is_empty() {
+        Ok(sports)
+    } else {
+        Err(ValidationErrors(errors))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FieldTypeArg {
+    String,
+    Integer,
+    Date,
+    Boolean,
+    Enum,
 }
 
 pub fn should_run_cli() -> bool {
@@ -60,34 +433,157 @@ pub fn should_run_cli() -> bool {
 pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    if let Some(command) = cli.command {
-        handle_command(cli.file, command)?;
-    } else {
-        interactive_cli(cli.file)?;
+    let store = open_store(&cli.file, cli.format);
+    let load_options = LoadOptions {
+        lenient: cli.lenient || cli.quarantine.is_some(),
+        quarantine: cli.quarantine,
+    };
+    match cli.command {
+        // The sport catalog does not belong to the database, which may not exist yet
+        Some(Commands::Sports { command }) => handle_sports_command(command)?,
+        Some(command) => handle_command(store.as_ref(), command, cli.id_strategy, &load_options)?,
+        None => interactive_cli(store.as_ref(), cli.id_strategy, &load_options)?,
     }
 
     Ok(())
 }
 
-pub fn handle_command(file: String, command: Commands) -> Result<(), Box<dyn std::error::Error>> {
-    let mut people = Person::read_from_csv(&file)?;
+/// How records that cannot be read are handled when the database is loaded.
+#[derive(Default)]
+pub struct LoadOptions {
+    /// Skip unreadable records instead of failing
+    pub lenient: bool,
+    /// File the skipped records are saved to
+    pub quarantine: Option<String>,
+}
+
+/// Loads the database, reporting any skipped records on stderr when loading leniently.
+fn load_people(store: &dyn PeopleStore, options: &LoadOptions) -> Result<Vec<Person>, Box<dyn std::error::Error>> {
+    if !options.lenient {
+        return store.load();
+    }
+    let (people, problems) = store.load_lenient()?;
+    if problems.is_empty() {
+        return Ok(people);
+    }
+    eprintln!(
+        "Skipped {} {} in {}:",
+        problems.len(),
+        if problems.len() == 1 { "record" } else { "records" },
+        store.path().display()
+    );
+    for problem in &problems {
+        eprintln!("  {}", problem);
+    }
+    match &options.quarantine {
+        Some(destination) => {
+            store.quarantine(&problems, Path::new(destination))?;
+            eprintln!("Saved the skipped records to {}", destination);
+        }
+        None => eprintln!("Warning: skipped records are removed from the file when it is saved; use --quarantine FILE to keep them"),
+    }
+    Ok(people)
+}
+
+/// Parses the date ages are shown for.
+fn parse_as_of(date: &str) -> Result<NaiveDate, Box<dyn std::error::Error>> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date).into())
+}
+
+/// Prints the people whose name matches `text`, or sounds like it, best match first.
+fn print_search_results(people: &[Person], text: &str, sounds_like: bool, schema: &FieldSchema) {
+    let found = if sounds_like { search_sounds_like(people, text) } else { search(people, text) };
+    if found.is_empty() {
+        println!("No one matches '{}'", text.trim());
+    } else {
+        print_people_at(people, &found, today(), schema);
+    }
+}
+
+/// Resolves a person selected either by index or by `--id` to an index.
+fn resolve_index(
+    people: &[Person],
+    index: Option<usize>,
+    id: Option<&str>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    match (index, id) {
+        (_, Some(id)) => find_person_by_id(people, id).ok_or_else(|| format!("No person with ID {}", id).into()),
+        (Some(index), None) if index < people.len() => Ok(index),
+        _ => Err("Index out of bounds".into()),
+    }
+}
+
+/// Warns when an interactive editor has the database open, since its next save may
+/// conflict with the change about to be made.
+fn warn_if_locked(store: &dyn PeopleStore) {
+    if let Some(owner) = LockFile::current_owner(store.path()) {
+        eprintln!("Warning: {} is open in another editor: {}", store.path().display(), owner);
+    }
+}
+
+pub fn handle_command(store: &dyn PeopleStore, command: Commands, id_strategy: Option<IdStrategy>, load_options: &LoadOptions) -> Result<(), Box<dyn std::error::Error>> {
+    // Importing is also how a new database is created from an existing file
+    if matches!(command, Commands::Import { .. }) && !store.path().exists() {
+        store.create()?;
+    }
+    let mut people = load_people(store, load_options)?;
+    let mut schema = FieldSchema::load_for(store.path())?;
 
     match command {
-        Commands::Print => print_people(&people),
-        Commands::Delete { index } => {
-            delete_person(&mut people, index)?;
-            Person::write_to_csv(&file, &people)?;
+        Commands::Print { as_of, tags, filter } => {
+            let as_of = match as_of {
+                Some(date) => parse_as_of(&date)?,
+                None => today(),
+            };
+            let tags = parse_tags(&tags)?;
+            let filter = filter.map(|f| Filter::parse(&f, &schema)).transpose()?;
+            print_people(&people, as_of, &schema, &|p: &Person| {
+                tags.iter().all(|t| p.has_tag(t)) && filter.as_ref().is_none_or(|f| f.matches(p, as_of))
+            });
+        }
+        Commands::Search { text, sounds_like } => print_search_results(&people, &text.join(" "), sounds_like, &schema),
+        Commands::Duplicates => {
+            let duplicates = find_duplicates(&people);
+            if duplicates.is_empty() {
+                println!("No likely duplicates");
+            }
+            for duplicate in duplicates {
+                let describe = |p: &Person| format!("{} {} (ID {})", p.first_name, p.last_name, p.id);
+                println!(
+                    "{} and {}: {}",
+                    describe(&people[duplicate.first]),
+                    describe(&people[duplicate.second]),
+                    duplicate.reasons.join(", ")
+                );
+            }
+        }
+        Commands::Delete { index, id } => {
+            let index = resolve_index(&people, index, id.as_deref())?;
+            warn_if_locked(store);
+            if load_options.lenient {
+                // The store would load the database again and fail on the skipped records
+                delete_person(&mut people, index)?;
+                store.save(&people)?;
+                let mut relations = store.load_relations()?;
+                if retain_valid(&mut relations, &people) > 0 {
+                    store.save_relations(&relations)?;
+                }
+            } else {
+                store.delete(&people[index].id)?;
+            }
         }
         Commands::Edit {
             index,
+            id,
             first_name,
             last_name,
             date_of_birth,
-            favorite_sport,
+            favorite_sports,
+            contact,
+            fields,
         } => {
-            if index >= people.len() {
-                return Err("Index out of bounds".into());
-            }
+            let index = resolve_index(&people, index, id.as_deref())?;
 
             let mut person = people[index].clone();
             if let Some(first_name_val) = first_name {
@@ -96,61 +592,470 @@ pub fn handle_command(file: String, command: Commands) -> Result<(), Box<dyn std
             if let Some(last_name_val) = last_name {
                 person.last_name = last_name_val;
             }
+            let mut errors = Vec::new();
             if let Some(dob) = date_of_birth {
-                person.date_of_birth = NaiveDate::parse_from_str(&dob, "%Y-%m-%d")?;
+                match parse_date_of_birth(&dob) {
+                    Ok(date) => person.date_of_birth = date,
+                    Err(e) => errors.push(e),
+                }
             }
-            if let Some(sport) = favorite_sport {
-                person.favorite_sport = Sport::from_string(&sport);
+            if !favorite_sports.is_empty() {
+                person.favorite_sports = parse_list(&favorite_sports, parse_sport, &mut errors);
             }
+            errors.extend(contact.apply(&mut person));
+            if !errors.is_empty() {
+                return Err(ValidationErrors(errors).into());
+            }
+            set_custom_fields(&mut person, &schema, &fields)?;
 
             edit_person(&mut people, index, person)?;
-            Person::write_to_csv(&file, &people)?;
+            warn_if_locked(store);
+            if load_options.lenient {
+                store.save(&people)?;
+            } else {
+                store.update(&people[index])?;
+            }
         }
         Commands::New {
             first_name,
             last_name,
             date_of_birth,
-            favorite_sport,
+            favorite_sports,
+            contact,
+            fields,
         } => {
-            let person =
-                create_person_from_args(first_name, last_name, date_of_birth, favorite_sport)?;
-            add_person(&mut people, person)?;
-            Person::write_to_csv(&file, &people)?;
+            let mut person =
+                create_person_from_args(first_name, last_name, date_of_birth, favorite_sports, contact)?;
+            set_custom_fields(&mut person, &schema, &fields)?;
+            let strategy = id_strategy.unwrap_or_else(|| IdStrategy::used_by(&people));
+            add_person(&mut people, person, strategy)?;
+            warn_if_locked(store);
+            if load_options.lenient {
+                store.save(&people)?;
+            } else {
+                store.insert(people.last().unwrap())?;
+            }
+        }
+        Commands::Import { source, format } => {
+            let mut relations = store.load_relations()?;
+            let outcome = import_from(&source, format, &mut people, &mut relations, &mut schema)?;
+            for problem in &outcome.problems {
+                eprintln!("Skipped {}", problem);
+            }
+            if outcome.new_fields {
+                schema.save_for(store.path())?;
+            }
+            warn_if_locked(store);
+            store.save(&people)?;
+            if outcome.new_relations {
+                store.save_relations(&relations)?;
+            }
+            let (added, updated) = (outcome.added, outcome.updated);
+            println!("Imported {} from {}: {} added, {} updated", added + updated, source, added, updated);
+        }
+        Commands::Export { destination, format } => {
+            export_to(&destination, format, &people, &store.load_relations()?, &schema)?;
+            println!("Exported {} {} to {}", people.len(), if people.len() == 1 { "person" } else { "people" }, destination);
+        }
+        Commands::Migrate { dry_run } => {
+            let pending = store.pending_migrations()?;
+            if pending.is_empty() {
+                println!("{} is already at the current schema version", store.path().display());
+            } else {
+                println!("Migrations for {}:", store.path().display());
+                for migration in &pending {
+                    println!("  {}", migration);
+                }
+                if dry_run {
+                    println!("Dry run, nothing was changed");
+                } else {
+                    warn_if_locked(store);
+                    store.save(&people)?;
+                    println!("Migrated {}", store.path().display());
+                }
+            }
+        }
+        Commands::Birthdays { destination, sports } => {
+            let sports = parse_sports(&sports)?;
+            ical::write_birthday_calendar(&destination, &people, &sports)?;
+            println!("Wrote birthday calendar to {}", destination);
+        }
+        Commands::Sports { command } => handle_sports_command(command)?,
+        Commands::RepairSports { language, dry_run } => {
+            let language = language
+                .or_else(current_language)
+                .ok_or("Cannot tell the language from the locale, use --language")?;
+            // Read the file again, this time taking names in that language to be translations
+            prefer_language(Some(&language));
+            let repaired = load_people(store, load_options);
+            prefer_language(None);
+            let repaired = repaired?;
+
+            let mut changed = 0;
+            for (before, after) in people.iter().zip(&repaired) {
+                if before.favorite_sports != after.favorite_sports {
+                    println!(
+                        "{} {} ({}): {} -> {}",
+                        after.first_name,
+                        after.last_name,
+                        after.id,
+                        before.sports_list(),
+                        after.sports_list()
+                    );
+                    changed += 1;
+                }
+            }
+            let mut unknown: Vec<&str> = Vec::new();
+            for sport in repaired.iter().flat_map(|p| &p.favorite_sports) {
+                if let (Sport::Other(name), None) = (sport, sport.key()) {
+                    if !unknown.contains(&name.as_str()) {
+                        unknown.push(name);
+                    }
+                }
+            }
+            if !unknown.is_empty() {
+                println!("Not in the sport catalog, left as they are: {}", unknown.join(", "));
+            }
+            if dry_run {
+                println!("Dry run, nothing was changed");
+            } else {
+                warn_if_locked(store);
+                store.save(&repaired)?;
+                println!(
+                    "Repaired the sports of {} {}; all sports in {} are now stored by key",
+                    changed,
+                    if changed == 1 { "person" } else { "people" },
+                    store.path().display()
+                );
+            }
+        }
+        Commands::Fields { command } => match command {
+            FieldsCommand::List => {
+                if schema.fields.is_empty() {
+                    println!("No custom fields");
+                }
+                for field in &schema.fields {
+                    match &field.label {
+                        Some(label) => println!("{} ({}): {}", field.name, label, field.field_type),
+                        None => println!("{}: {}", field.name, field.field_type),
+                    }
+                }
+            }
+            FieldsCommand::Add { name, field_type, options, label } => {
+                let field_type = match field_type {
+                    FieldTypeArg::String => FieldType::String,
+                    FieldTypeArg::Integer => FieldType::Integer,
+                    FieldTypeArg::Date => FieldType::Date,
+                    FieldTypeArg::Boolean => FieldType::Boolean,
+                    FieldTypeArg::Enum => FieldType::Enum { options },
+                };
+                schema.add(FieldDefinition { name: name.clone(), label, field_type })?;
+                schema.save_for(store.path())?;
+                println!("Added field {}", name);
+            }
+            FieldsCommand::Remove { name } => {
+                schema.remove(&name)?;
+                schema.save_for(store.path())?;
+                if people.iter().any(|p| p.custom.contains_key(&name)) {
+                    for person in &mut people {
+                        person.custom.remove(&name);
+                    }
+                    warn_if_locked(store);
+                    store.save(&people)?;
+                }
+                println!("Removed field {}", name);
+            }
+        },
+        Commands::Relations { id } => {
+            let index = find_person_by_id(&people, &id).ok_or_else(|| format!("No person with ID {}", id))?;
+            let person = &people[index];
+            let relations = store.load_relations()?;
+            let related = related_people(&relations, &people, &id);
+            if related.is_empty() {
+                println!("{} {} ({}) has no relationships", person.first_name, person.last_name, person.id);
+            } else {
+                println!("Relationships of {} {} ({}):", person.first_name, person.last_name, person.id);
+                for (description, other) in related {
+                    println!("  {} {} {} ({})", description, other.first_name, other.last_name, other.id);
+                }
+            }
+        }
+        Commands::Relate { from_id, kind, to_id } => {
+            let kind = RelationKind::parse(&kind)?;
+            let mut relations = store.load_relations()?;
+            let relation = Relation { from_id, kind, to_id };
+            let description = format!("{} is {} {}", relation.from_id, relation.kind.describe(true), relation.to_id);
+            add_relation(&mut relations, &people, relation)?;
+            store.save_relations(&relations)?;
+            println!("Recorded that {}", description);
+        }
+        Commands::Unrelate { first_id, second_id } => {
+            let mut relations = store.load_relations()?;
+            let removed = remove_relations_between(&mut relations, &first_id, &second_id);
+            if removed == 0 {
+                return Err(format!("{} and {} are not related", first_id, second_id).into());
+            }
+            store.save_relations(&relations)?;
+            println!("Removed {} {}", removed, if removed == 1 { "relationship" } else { "relationships" });
         }
+        Commands::Tag { command } => match command {
+            TagCommand::List => {
+                let counts = tag_counts(&people);
+                if counts.is_empty() {
+                    println!("No tags");
+                }
+                for (tag, count) in counts {
+                    println!("{} ({})", tag, count);
+                }
+            }
+            TagCommand::Add { tags, selection } => {
+                let tags = parse_tags(&tags)?;
+                let changed = change_tags(&mut people, &selection, &schema, &tags, Person::add_tag)?;
+                if changed > 0 {
+                    warn_if_locked(store);
+                    store.save(&people)?;
+                }
+                println!("Tagged {} {} with {}", changed, if changed == 1 { "person" } else { "people" }, tags.join(", "));
+            }
+            TagCommand::Remove { tags, selection } => {
+                let tags = parse_tags(&tags)?;
+                let changed = change_tags(&mut people, &selection, &schema, &tags, Person::remove_tag)?;
+                if changed > 0 {
+                    warn_if_locked(store);
+                    store.save(&people)?;
+                }
+                println!("Removed {} from {} {}", tags.join(", "), changed, if changed == 1 { "person" } else { "people" });
+            }
+        },
     }
 
     Ok(())
 }
 
+fn handle_sports_command(command: SportsCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        SportsCommand::List => {
+            for entry in &catalog().sports {
+                let mut line = format!("{}: {} {}", entry.key, entry.emoji, entry.localized_name());
+                if !entry.category.is_empty() {
+                    line.push_str(&format!(" ({})", entry.category));
+                }
+                if !entry.aliases.is_empty() {
+                    line.push_str(&format!(", also {}", entry.aliases.join(", ")));
+                }
+                println!("{}", line);
+            }
+        }
+        SportsCommand::Add { key, name, emoji, aliases, category, translations } => {
+            let mut entry = SportEntry {
+                key: key.clone(),
+                name: name.trim().to_string(),
+                emoji: emoji.unwrap_or_default().trim().to_string(),
+                aliases: aliases.iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect(),
+                category: category.unwrap_or_default().trim().to_lowercase(),
+                translations: Default::default(),
+            };
+            for translation in &translations {
+                let (language, name) = translation
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid translation '{}', expected LANG=NAME", translation))?;
+                entry.translations.insert(language.trim().to_string(), name.trim().to_string());
+            }
+            let mut user_catalog = SportCatalog::load_user()?;
+            user_catalog.add(entry, catalog())?;
+            let path = user_catalog.save_user()?;
+            println!("Added sport {} to {}", key, path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Builds a person from the `new` arguments. The names and a sport are required and the date
+/// of birth and contact details are optional; all missing or invalid fields are reported together.
 fn create_person_from_args(
     first_name: Option<String>,
     last_name: Option<String>,
     date_of_birth: Option<String>,
-    favorite_sport: Option<String>,
+    favorite_sports: Vec<String>,
+    contact: ContactArgs,
 ) -> Result<Person, Box<dyn std::error::Error>> {
-    let first_name = first_name.unwrap_or_else(|| "Unknown".to_string());
-    let last_name = last_name.unwrap_or_else(|| "Unknown".to_string());
-    let date_of_birth = if let Some(dob) = date_of_birth {
-        NaiveDate::parse_from_str(&dob, "%Y-%m-%d")?
-    } else {
-        NaiveDate::from_ymd_opt(1900, 1, 1).unwrap()
-    };
-    let favorite_sport = if let Some(s) = favorite_sport {
-        Sport::from_string(&s)
+    let date_of_birth = parse_date_of_birth(date_of_birth.as_deref().unwrap_or(""));
+    let mut sport_errors = Vec::new();
+    let mut person = Person::new(
+        first_name.unwrap_or_default(),
+        last_name.unwrap_or_default(),
+        date_of_birth.clone().unwrap_or_default(),
+        parse_list(&favorite_sports, parse_sport, &mut sport_errors),
+    );
+
+    let mut errors: Vec<ValidationError> = [
+        validate_name(Field::FirstName, &person.first_name).err(),
+        validate_name(Field::LastName, &person.last_name).err(),
+        date_of_birth.err(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if favorite_sports.iter().all(|s| s.trim().is_empty()) {
+        errors.push(ValidationError::Empty(Field::FavoriteSport));
+    }
+    errors.extend(sport_errors);
+    errors.extend(contact.apply(&mut person));
+    if errors.is_empty() {
+        Ok(person)
     } else {
-        Sport::Other("Unknown".to_string())
-    };
+        Err(ValidationErrors(errors).into())
+    }
+}
+
+/// Sets custom fields from `NAME=VALUE` arguments. An empty value clears the field.
+fn set_custom_fields(person: &mut Person, schema: &FieldSchema, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid field '{}', expected NAME=VALUE", arg))?;
+        let field = schema
+            .get(name.trim())
+            .ok_or_else(|| format!("Unknown field '{}'; see the fields command", name.trim()))?;
+        let value = field.parse_value(value)?;
+        if value.is_empty() {
+            person.custom.remove(&field.name);
+        } else {
+            person.custom.insert(field.name.clone(), value);
+        }
+    }
+    Ok(())
+}
+
+/// Asks for the value of each custom field. When editing, blank input keeps the current
+/// value and `-` clears it.
+fn prompt_custom_fields(person: &mut Person, schema: &FieldSchema, editing: bool) -> io::Result<()> {
+    for field in &schema.fields {
+        let current = person.custom.get(&field.name).cloned();
+        let prompt = match (&current, editing) {
+            (Some(current), true) => format!(
+                "Enter new {} ({}) [{}] (or leave blank, - to clear): ",
+                field.label(),
+                field.hint(),
+                current
+            ),
+            _ => format!("Enter {} ({}) (or leave blank): ", field.label(), field.hint()),
+        };
+        let value = prompt_until_valid(&prompt, |input| match input {
+            "" if editing => Ok(current.clone().unwrap_or_default()),
+            "-" if editing => Ok(String::new()),
+            _ => field.parse_value(input),
+        })?;
+        if value.is_empty() {
+            person.custom.remove(&field.name);
+        } else {
+            person.custom.insert(field.name.clone(), value);
+        }
+    }
+    Ok(())
+}
+
+/// Reads a line from stdin after printing `prompt`, asking again until `parse` accepts it.
+fn prompt_until_valid<T, E: std::fmt::Display>(
+    prompt: &str,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> io::Result<T> {
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        match parse(input.trim()) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("  {}", e),
+        }
+    }
+}
+
+/// Saves from the interactive shell. If another program changed the file since it was
+/// loaded, asks whether to merge, overwrite or reload. Returns true when memory and file
+/// are in sync afterwards.
+fn save_interactive(
+    store: &dyn PeopleStore,
+    people: &mut Vec<Person>,
+    base_people: &mut Vec<Person>,
+    relations: &mut Vec<Relation>,
+    fingerprint: &mut Option<FileFingerprint>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let file = store.path().display();
+    if fingerprint.as_ref().is_some_and(|f| f.has_changed(store.path())) {
+        println!("Warning: {} was changed by another program since it was loaded.", file);
+        let options = vec![
+            "Merge their changes with yours",
+            "Overwrite their changes",
+            "Discard your changes and reload",
+            "Cancel",
+        ];
+        let choice = Select::new("How do you want to continue?", options.clone()).prompt();
+        match choice {
+            Ok(c) if c == options[0] => {
+                let theirs = store.load()?;
+                let outcome = merge_people(base_people, people, &theirs);
+                for conflict in &outcome.conflicts {
+                    println!("  Conflict: {}", conflict);
+                }
+                *people = outcome.people;
+            }
+            Ok(c) if c == options[1] => {}
+            Ok(c) if c == options[2] => {
+                *fingerprint = FileFingerprint::of(store.path()).ok();
+                *people = store.load()?;
+                *base_people = people.clone();
+                *relations = store.load_relations()?;
+                println!("Reloaded {}", file);
+                return Ok(true);
+            }
+            _ => {
+                println!("Save cancelled");
+                return Ok(false);
+            }
+        }
+    }
 
-    Ok(Person::new(
-        first_name,
-        last_name,
-        date_of_birth,
-        favorite_sport,
-    ))
+    store.save(people)?;
+    // The relationships of people deleted in the shell are already gone; a merge may have
+    // dropped others
+    retain_valid(relations, people);
+    if store.load_relations()? != *relations {
+        store.save_relations(relations)?;
+    }
+    *fingerprint = FileFingerprint::of(store.path()).ok();
+    *base_people = people.clone();
+    println!("Saved to {}", file);
+    Ok(true)
 }
 
-pub fn interactive_cli(file: String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut people = Person::read_from_csv(&file)?;
+pub fn interactive_cli(store: &dyn PeopleStore, id_strategy: Option<IdStrategy>, load_options: &LoadOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let file = store.path().display();
+    let mut fingerprint = FileFingerprint::of(store.path()).ok();
+    let mut people = load_people(store, load_options)?;
+    let mut relations = store.load_relations()?;
+    let schema = FieldSchema::load_for(store.path())?;
+    let mut base_people = people.clone();
+    let lock = match LockFile::acquire(store.path()) {
+        Ok(LockStatus::Acquired(lock)) => Ok(Some(lock)),
+        Ok(LockStatus::HeldBy(owner)) => {
+            println!("Warning: {} is already open in another editor: {}", file, owner);
+            // The other editor may have been closed without removing its lock
+            let take_over = Confirm::new("Take over the lock? Only do so if that editor is closed")
+                .with_default(false)
+                .prompt()
+                .unwrap_or(false);
+            if take_over { LockFile::take_over(store.path()).map(Some) } else { Ok(None) }
+        }
+        Err(e) => Err(e),
+    };
+    let _lock = lock.unwrap_or_else(|e| {
+        log::warn!("Could not create lock file for {}: {}", file, e);
+        None
+    });
     let mut unsaved_changes = false;
     let mut rl = Editor::<(), FileHistory>::new()?;
 
@@ -193,14 +1098,50 @@ pub fn interactive_cli(file: String) -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                     "save" | "write" | "s" | "w" => {
-                        Person::write_to_csv(&file, &people)?;
-                        unsaved_changes = false;
-                        println!("Saved to {}", file);
+                        match save_interactive(store, &mut people, &mut base_people, &mut relations, &mut fingerprint) {
+                            Ok(true) => unsaved_changes = false,
+                            Ok(false) => {}
+                            Err(e) => println!("Error: could not save: {}", e),
+                        }
+                    }
+                    "print" | "p" => {
+                        // print [date] [where <filter>]
+                        let (before, filter) = match line.split_once(" where ") {
+                            Some((before, filter)) => (before, Some(filter)),
+                            None => (line.as_str(), None),
+                        };
+                        let as_of = match before.split_whitespace().nth(1).map(parse_as_of) {
+                            Some(Ok(as_of)) => as_of,
+                            Some(Err(e)) => {
+                                println!("Error: {}", e);
+                                continue;
+                            }
+                            None => today(),
+                        };
+                        match filter.map(|f| Filter::parse(f, &schema)).transpose() {
+                            Ok(filter) => print_people(&people, as_of, &schema, &|p: &Person| {
+                                filter.as_ref().is_none_or(|f| f.matches(p, as_of))
+                            }),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    }
+                    "search" | "f" => {
+                        let text = line.split_once(char::is_whitespace).map_or("", |(_, text)| text);
+                        let (text, sounds_like) = match text.trim().strip_prefix("--sounds-like") {
+                            Some(text) => (text, true),
+                            None => (text, false),
+                        };
+                        if text.trim().is_empty() {
+                            println!("Usage: search [--sounds-like] <text>");
+                        } else {
+                            print_search_results(&people, text, sounds_like, &schema);
+                        }
                     }
-                    "print" | "p" => print_people(&people),
                     "delete" | "d" => {
                         if let Some(index) = args.first().and_then(|s| s.parse::<usize>().ok()) {
                             if delete_person(&mut people, index).is_ok() {
+                                // Someone added later may be given the same ID
+                                retain_valid(&mut relations, &people);
                                 unsaved_changes = true;
                                 println!("Person at index {} deleted", index);
                             } else {
@@ -220,50 +1161,41 @@ pub fn interactive_cli(file: String) -> Result<(), Box<dyn std::error::Error>> {
                             let mut person = people[index].clone();
                             println!("Editing person at index {}: {}", index, person.first_name);
 
-                            // Interactive editing
-                            print!("Enter new first name (or leave blank): ");
-                            io::stdout().flush()?;
-                            let mut input = String::new();
-                            io::stdin().read_line(&mut input)?;
-                            let input_trimmed = input.trim();
-                            if !input_trimmed.is_empty() {
-                                person.first_name = input_trimmed.to_string();
-                            }
-
-                            print!("Enter new last name (or leave blank): ");
-                            io::stdout().flush()?;
-                            input.clear();
-                            io::stdin().read_line(&mut input)?;
-                            let input_trimmed = input.trim();
-                            if !input_trimmed.is_empty() {
-                                person.last_name = input_trimmed.to_string();
-                            }
-
-                            print!("Enter new date of birth (YYYY-MM-DD) (or leave blank): ");
-                            io::stdout().flush()?;
-                            input.clear();
-                            io::stdin().read_line(&mut input)?;
-                            let input_trimmed = input.trim();
-                            if !input_trimmed.is_empty() {
-                                if let Ok(date) =
-                                    NaiveDate::parse_from_str(input_trimmed, "%Y-%m-%d")
-                                {
-                                    person.date_of_birth = date;
+                            // Interactive editing; blank input keeps the current value
+                            let keep_or_name = |field: Field, current: &str, input: &str| {
+                                if input.is_empty() {
+                                    Ok(current.to_string())
                                 } else {
-                                    println!("Invalid date format. Keeping existing date.");
+                                    validate_name(field, input).map(|_| input.to_string())
                                 }
-                            }
+                            };
+                            person.first_name = prompt_until_valid("Enter new first name (or leave blank): ", |input| {
+                                keep_or_name(Field::FirstName, &person.first_name, input)
+                            })?;
+                            person.last_name = prompt_until_valid("Enter new last name (or leave blank): ", |input| {
+                                keep_or_name(Field::LastName, &person.last_name, input)
+                            })?;
+                            person.date_of_birth = prompt_until_valid("Enter new date of birth (YYYY-MM-DD, YYYY, --MM-DD, or - if unknown) (or leave blank): ", |input| {
+                                match input {
+                                    "" => Ok(person.date_of_birth),
+                                    "-" => Ok(BirthDate::Unknown),
+                                    _ => parse_date_of_birth(input),
+                                }
+                            })?;
 
-                            // Use sport menu with current sport as default
-                            println!("Edit favorite sport (or leave blank to keep current):");
-                            let sport_input = prompt_for_sport_with_default(Some(&person.favorite_sport));
-                            if let Some(sport) = sport_input {
-                                person.favorite_sport = sport;
+                            // Use sport menu with the current sports selected
+                            println!("Edit favorite sports (or press Esc to keep current):");
+                            if let Some(sports) = prompt_for_sports_with_default(&person.favorite_sports) {
+                                person.favorite_sports = sports;
                             }
+                            prompt_custom_fields(&mut person, &schema, true)?;
 
-                            if edit_person(&mut people, index, person).is_ok() {
-                                unsaved_changes = true;
-                                println!("Person updated successfully");
+                            match edit_person(&mut people, index, person) {
+                                Ok(()) => {
+                                    unsaved_changes = true;
+                                    println!("Person updated successfully");
+                                }
+                                Err(e) => println!("Error: {}", e),
                             }
                         } else {
                             println!("Usage: edit <index>");
@@ -272,55 +1204,48 @@ pub fn interactive_cli(file: String) -> Result<(), Box<dyn std::error::Error>> {
                     "new" | "n" => {
                         println!("Adding new person:");
 
-                        print!("Enter first name: ");
-                        io::stdout().flush()?;
-                        let mut first_name = String::new();
-                        io::stdin().read_line(&mut first_name)?;
-                        let first_name = first_name.trim().to_string();
-
-                        print!("Enter last name: ");
-                        io::stdout().flush()?;
-                        let mut last_name = String::new();
-                        io::stdin().read_line(&mut last_name)?;
-                        let last_name = last_name.trim().to_string();
-
-                        print!("Enter date of birth (YYYY-MM-DD): ");
-                        io::stdout().flush()?;
-                        let mut date_input = String::new();
-                        io::stdin().read_line(&mut date_input)?;
-                        let date_input = date_input.trim();
-
+                        let first_name = prompt_until_valid("Enter first name: ", |input| {
+                            validate_name(Field::FirstName, input).map(|_| input.to_string())
+                        })?;
+                        let last_name = prompt_until_valid("Enter last name: ", |input| {
+                            validate_name(Field::LastName, input).map(|_| input.to_string())
+                        })?;
                         let date_of_birth =
-                            if let Ok(date) = NaiveDate::parse_from_str(date_input, "%Y-%m-%d") {
-                                date
-                            } else {
-                                println!("Invalid date format. Using 1900-01-01 as default.");
-                                NaiveDate::from_ymd_opt(1900, 1, 1).unwrap()
-                            };
+                            prompt_until_valid("Enter date of birth (YYYY-MM-DD, YYYY, --MM-DD, or blank if unknown): ", parse_date_of_birth)?;
 
                         // Use sport menu
-                        let favorite_sport =
-                            prompt_for_sport().unwrap_or(Sport::Other("Unknown".to_string()));
-                        let person =
-                            Person::new(first_name, last_name, date_of_birth, favorite_sport);
-                        if add_person(&mut people, person).is_ok() {
-                            unsaved_changes = true;
-                            println!("Person added successfully");
+                        let Some(favorite_sports) = prompt_for_sports() else {
+                            println!("No sport chosen, person not added");
+                            continue;
+                        };
+                        let mut person =
+                            Person::new(first_name, last_name, date_of_birth, favorite_sports);
+                        prompt_custom_fields(&mut person, &schema, false)?;
+                        let strategy = id_strategy.unwrap_or_else(|| IdStrategy::used_by(&people));
+                        match add_person(&mut people, person, strategy) {
+                            Ok(()) => {
+                                unsaved_changes = true;
+                                println!("Person added successfully");
+                            }
+                            Err(e) => println!("Error: {}", e),
                         }
                     }
                     "help" | "h" => {
                         println!("Available commands:");
-                        println!("  print, p          - Display all people");
+                        println!("  print, p [date]   - Display all people, with ages on date (YYYY-MM-DD)");
+                        println!("  print [date] where <filter> - Display the people matching a filter, e.g. age > 30 and sport = tennis");
+                        println!("  search, f <text>  - Find people by name");
+                        println!("  search --sounds-like <text> - Find people whose name sounds like text");
                         println!("  new, n            - Add a new person");
                         println!("  edit <index>, e   - Edit person at index");
                         println!("  delete <index>, d - Delete person at index");
                         println!("  save/write, s/w   - Save changes to file");
                         println!("  exit, quit        - Exit the program");
                         println!("  help, h           - Show this help");
-                        println!("  Note: favorite_sport only accepts known values.");
+                        println!("  Favorite sports are picked from the catalog, or typed in under Other...");
                         let valid_sports = Sport::all_known_sports();
                         println!(
-                            "  Valid options: {}",
+                            "  Sports in the catalog: {}",
                             valid_sports
                                 .iter()
                                 .map(|s| s.to_string())
@@ -345,66 +1270,79 @@ pub fn interactive_cli(file: String) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn prompt_for_sport() -> Option<Sport> {
-    prompt_for_sport_with_default(None)
+fn prompt_for_sports() -> Option<Vec<Sport>> {
+    prompt_for_sports_with_default(&[])
 }
 
-fn prompt_for_sport_with_default(default_sport: Option<&Sport>) -> Option<Sport> {
+/// Asks for one or more favorite sports, then for their order when there are several.
+/// The `current` sports start out selected. Returns `None` if nothing was chosen.
+fn prompt_for_sports_with_default(current: &[Sport]) -> Option<Vec<Sport>> {
     let mut sport_variants = Sport::all_known_sports();
     sport_variants.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
-    let mut options: Vec<String> = sport_variants
+    let mut options: Vec<String> = sport_variants.iter().map(sport_label).collect();
+    options.push("Other...".to_string());
+
+    // Custom sports are all behind "Other...", which starts out selected if there are any
+    let current_custom: Vec<String> = current
         .iter()
-        .map(|sport| format!("{} {}", capitalize_first(&sport.to_string()), sport.emoji()))
+        .filter_map(|s| match s {
+            Sport::Other(name) => Some(name.clone()),
+            _ => None,
+        })
         .collect();
-    options.push("Other...".to_string());
-    
-    // Try a different approach - manually set the default by reordering options
-    let mut reordered_options = options.clone();
-    if let Some(default) = default_sport {
-        if let Sport::Other(_) = default {
-            // For "Other" sports, move "Other..." to the top
-            if let Some(other_pos) = reordered_options.iter().position(|opt| opt == "Other...") {
-                let other_option = reordered_options.remove(other_pos);
-                reordered_options.insert(0, other_option);
-            }
-        } else {
-            // Move the default sport to the top
-            let default_display = format!("{} {}", capitalize_first(&default.to_string()), default.emoji());
-            if let Some(default_pos) = reordered_options.iter().position(|opt| opt == &default_display) {
-                let default_option = reordered_options.remove(default_pos);
-                reordered_options.insert(0, default_option);
-            }
-        }
+    let mut defaults: Vec<usize> = current
+        .iter()
+        .filter_map(|s| sport_variants.iter().position(|v| v == s))
+        .collect();
+    if !current_custom.is_empty() {
+        defaults.push(options.len() - 1);
     }
-    
-    println!("Choose a favorite sport:");
-    let ans = Select::new("Select a sport:", reordered_options.clone())
-        .prompt();
-    match ans {
-        Ok(choice) => {
-            if choice == "Other..." {
-                let custom = Text::new("Enter custom sport:").prompt();
-                match custom {
-                    Ok(val) => {
-                        let trimmed = val.trim();
-                        if trimmed.is_empty() {
-                            None
-                        } else {
-                            Some(Sport::Other(trimmed.to_string()))
-                        }
-                    }
-                    Err(_) => None,
-                }
-            } else {
-                // Find the original sport variant by matching the display string
-                let selected = sport_variants.iter().find(|sport| {
-                    format!("{} {}", capitalize_first(&sport.to_string()), sport.emoji()) == choice
-                });
-                selected.cloned()
-            }
+
+    println!("Choose favorite sports:");
+    let choices = MultiSelect::new("Select sports (space to select, enter to confirm):", options)
+        .with_default(&defaults)
+        .prompt()
+        .ok()?;
+    let mut chosen: Vec<Sport> = Vec::new();
+    for choice in choices {
+        if choice == "Other..." {
+            let custom = Text::new("Enter custom sports, separated by commas:")
+                .with_default(&current_custom.join(", "))
+                .prompt()
+                .ok()?;
+            chosen.extend(
+                custom
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(Sport::from_string),
+            );
+        } else if let Some(sport) = sport_variants.iter().find(|s| sport_label(s) == choice) {
+            chosen.push(sport.clone());
         }
-        Err(_) => None,
     }
+    if chosen.is_empty() {
+        return None;
+    }
+
+    // Sports that were already chosen keep their order, ahead of new ones
+    chosen.sort_by_key(|s| current.iter().position(|c| c == s).unwrap_or(usize::MAX));
+    let mut ranked = Vec::new();
+    while chosen.len() > 1 {
+        let message = if ranked.is_empty() { "Which is the primary sport?" } else { "Which comes next?" };
+        let labels: Vec<String> = chosen.iter().map(sport_label).collect();
+        // Escape keeps the remaining sports in their current order
+        let Ok(pick) = Select::new(message, labels).raw_prompt() else {
+            break;
+        };
+        ranked.push(chosen.remove(pick.index));
+    }
+    ranked.extend(chosen);
+    Some(ranked)
+}
+
+fn sport_label(sport: &Sport) -> String {
+    format!("{} {}", capitalize_first(&sport.to_string()), sport.emoji())
 }
 
 fn capitalize_first(s: &str) -> String {
@@ -447,7 +1385,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
     
     if should_run_cli() {
-        run()?;
+        if let Err(e) = run() {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     } else {
         // Print current directory and prompt for file path
         let cwd = std::env::current_dir()?;
@@ -470,12 +1411,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Err(e.into());
             }
         };
+        let store = open_store(&file, None);
         // If file doesn't exist, create it with headers
-        if !std::path::Path::new(&file).exists() {
+        if !store.path().exists() {
             println!("File '{}' does not exist. Creating new file...", file);
-            create_new_csv_file(&file)?;
+            store.create()?;
         }
-        interactive_cli(file)?;
+        interactive_cli(store.as_ref(), None, &LoadOptions::default())?;
     }
     Ok(())
 }
@@ -0,0 +1,247 @@
+use std::error::Error;
+
+/// Version of the CSV layout written by this build.
+///
+/// 1. `first_name,last_name,date_of_birth,favorite_sport`
+/// 2. adds the leading `id` column
+/// 3. unknown dates of birth are empty instead of `1900-01-01`
+/// 4. adds the `emails`, `phones`, `address` and `notes` columns
+/// 5. adds the `tags` column
+/// 6. `favorite_sport` becomes `favorite_sports`, a list with the primary sport first
+pub const SCHEMA_VERSION: u32 = 6;
+
+// First line of a CSV file that records its schema version, e.g. `#people-db schema=3`
+const METADATA_PREFIX: &str = "#people-db";
+
+/// A CSV database as plain text cells, before the records are turned into people.
+/// Migrations work on this form so they never depend on the current `Person` layout.
+#[derive(Debug, Clone)]
+pub struct CsvTable {
+    pub version: u32,
+    pub headers: Vec<String>,
+    /// Each row with the line it was read from
+    pub rows: Vec<(u64, Vec<String>)>,
+}
+
+struct Migration {
+    /// Version the migration upgrades from, to `from + 1`
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut CsvTable),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "add an empty id column, filled with new IDs when the file is read",
+        apply: add_id_column,
+    },
+    Migration {
+        from: 2,
+        description: "mark 1900-01-01 dates of birth as unknown",
+        apply: clear_placeholder_dates,
+    },
+    Migration {
+        from: 3,
+        description: "add empty contact detail columns",
+        apply: add_contact_columns,
+    },
+    Migration {
+        from: 4,
+        description: "add an empty tags column",
+        apply: add_tags_column,
+    },
+    Migration {
+        from: 5,
+        description: "rename favorite_sport to favorite_sports",
+        apply: rename_sport_column,
+    },
+];
+
+/// The metadata line written at the top of every CSV file.
+pub fn metadata_line() -> String {
+    format!("{} schema={}", METADATA_PREFIX, SCHEMA_VERSION)
+}
+
+impl CsvTable {
+    /// Parses CSV content, reading the schema version from the metadata line. Files
+    /// written before the metadata line existed get their version from the headers.
+    pub fn parse(content: &str) -> Result<CsvTable, Box<dyn Error>> {
+        let first_line = content.lines().next().unwrap_or("");
+        let (version, body, line_offset) = if first_line.starts_with(METADATA_PREFIX) {
+            let version = parse_metadata(first_line)
+                .ok_or_else(|| format!("Invalid metadata line: {}", first_line))?;
+            let body = content.split_once('\n').map_or("", |(_, rest)| rest);
+            (Some(version), body, 1)
+        } else {
+            (None, content, 0)
+        };
+
+        // Rows with the wrong number of fields are kept so they can be reported one by one
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(body.as_bytes());
+        let headers: Vec<String> = reader.headers()?.iter().map(|h| h.trim().to_string()).collect();
+        let mut rows = Vec::new();
+        for result in reader.records() {
+            let record = result?;
+            let line = record.position().map_or(0, |p| p.line()) + line_offset;
+            rows.push((line, record.iter().map(|s| s.to_string()).collect()));
+        }
+
+        let version = version.unwrap_or(if headers.iter().any(|h| h == "id") { 2 } else { 1 });
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "File uses schema version {}, but this version of people-db only understands up to {}",
+                version, SCHEMA_VERSION
+            )
+            .into());
+        }
+
+        Ok(CsvTable { version, headers, rows })
+    }
+
+    /// Describes the migrations needed to bring this table up to the current version.
+    pub fn pending_migrations(&self) -> Vec<String> {
+        MIGRATIONS
+            .iter()
+            .filter(|m| m.from >= self.version)
+            .map(|m| format!("{} -> {}: {}", m.from, m.from + 1, m.description))
+            .collect()
+    }
+
+    /// Upgrades the table to the current version. Returns descriptions of the applied migrations.
+    pub fn migrate(&mut self) -> Vec<String> {
+        let pending = self.pending_migrations();
+        let start = self.version;
+        for migration in MIGRATIONS.iter().filter(|m| m.from >= start) {
+            (migration.apply)(self);
+            self.version = migration.from + 1;
+        }
+        pending
+    }
+}
+
+fn parse_metadata(line: &str) -> Option<u32> {
+    // Spreadsheet apps may pad the line with empty cells
+    let line = line.trim_end_matches([',', '\r', ' ']);
+    line.split_whitespace()
+        .find_map(|part| part.strip_prefix("schema="))
+        .and_then(|v| v.parse().ok())
+}
+
+// The IDs are left empty for the reader to allocate, so that importing the file does not give
+// its people the IDs of others
+fn add_id_column(table: &mut CsvTable) {
+    table.headers.insert(0, "id".to_string());
+    for (_, row) in table.rows.iter_mut() {
+        row.insert(0, String::new());
+    }
+}
+
+// Before partial dates were supported, 1900-01-01 stood for an unknown date of birth
+fn clear_placeholder_dates(table: &mut CsvTable) {
+    let Some(column) = table.headers.iter().position(|h| h == "date_of_birth") else {
+        return;
+    };
+    for (_, row) in table.rows.iter_mut() {
+        if let Some(cell) = row.get_mut(column) {
+            if cell.trim() == "1900-01-01" {
+                cell.clear();
+            }
+        }
+    }
+}
+
+fn add_contact_columns(table: &mut CsvTable) {
+    add_missing_columns(table, &["emails", "phones", "address", "notes"]);
+}
+
+fn add_tags_column(table: &mut CsvTable) {
+    add_missing_columns(table, &["tags"]);
+}
+
+// A single sport is already a list of one, so only the header changes
+fn rename_sport_column(table: &mut CsvTable) {
+    if let Some(header) = table.headers.iter_mut().find(|h| *h == "favorite_sport") {
+        *header = "favorite_sports".to_string();
+    }
+}
+
+// Appends empty columns for each of `columns` the table does not have yet
+fn add_missing_columns(table: &mut CsvTable, columns: &[&str]) {
+    for &column in columns {
+        // A spreadsheet may already have added a column with the same name; its values are kept
+        if table.headers.iter().any(|h| h == column) {
+            continue;
+        }
+        table.headers.push(column.to_string());
+        for (_, row) in table.rows.iter_mut() {
+            row.push(String::new());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> CsvTable {
+        CsvTable::parse(content).unwrap()
+    }
+
+    #[test]
+    fn version_without_metadata_line_comes_from_the_headers() {
+        assert_eq!(parse("first_name,last_name,date_of_birth,favorite_sport\n").version, 1);
+        assert_eq!(parse("id,first_name,last_name,date_of_birth,favorite_sport\n").version, 2);
+    }
+
+    #[test]
+    fn metadata_line_padded_by_a_spreadsheet() {
+        let table = parse("#people-db schema=3,,,\r\nid,first_name,last_name,date_of_birth,favorite_sport\r\n");
+        assert_eq!(table.version, 3);
+        assert_eq!(table.headers[0], "id");
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        let error = CsvTable::parse("#people-db schema=7\nid\n").unwrap_err().to_string();
+        assert!(error.contains("schema version 7"), "{}", error);
+        assert!(CsvTable::parse("#people-db schema=x\nid\n").is_err());
+    }
+
+    #[test]
+    fn line_numbers_count_the_metadata_line() {
+        let content = "#people-db schema=6\nid,first_name\n0,Ann\n1,Bob\n";
+        let lines: Vec<u64> = parse(content).rows.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [3, 4]);
+
+        let lines: Vec<u64> = parse("id,first_name\n0,Ann\n").rows.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2]);
+    }
+
+    #[test]
+    fn migrates_version_1_to_the_current_layout() {
+        let mut table = parse(
+            "first_name,last_name,date_of_birth,favorite_sport,notes\n\
+             Ann,Miller,1900-01-01,Soccer,from the spreadsheet\n\
+             Bob,Smith,1990-05-01,Tennis,\n",
+        );
+        let applied = table.migrate();
+        assert_eq!(applied.len(), 5);
+        assert!(applied[0].starts_with("1 -> 2: "));
+        assert_eq!(table.version, SCHEMA_VERSION);
+        assert!(table.pending_migrations().is_empty());
+
+        assert_eq!(
+            table.headers,
+            [
+                "id", "first_name", "last_name", "date_of_birth", "favorite_sports", "notes", "emails",
+                "phones", "address", "tags"
+            ]
+        );
+        let rows: Vec<&Vec<String>> = table.rows.iter().map(|(_, row)| row).collect();
+        assert_eq!(rows[0], &["", "Ann", "Miller", "", "Soccer", "from the spreadsheet", "", "", "", ""]);
+        assert_eq!(rows[1], &["", "Bob", "Smith", "1990-05-01", "Tennis", "", "", "", "", ""]);
+    }
+}
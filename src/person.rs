@@ -1,48 +1,153 @@
+use crate::atomic_file::write_atomically;
+use crate::birth_date::BirthDate;
 use crate::constants::Sport;
-use chrono::Local;
-use chrono::NaiveDate;
+use crate::custom_fields::{FieldDefinition, FieldSchema};
+use crate::migrate::{metadata_line, CsvTable};
+use crate::store::LoadProblem;
+use crate::validation::{parse_phone, validate_change, ValidationErrors};
+use chrono::{Datelike, Local, Months, NaiveDate};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
-use std::fs::File;
+use std::fs;
+use std::io::Write;
 use std::path::Path;
-use tabled::{Table, Tabled};
-use crate::constants::CSV_HEADERS;
+use tabled::builder::Builder;
+use tabled::Tabled;
+use crate::constants::{CSV_HEADERS, CSV_LIST_HEADERS};
 use log;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-static COUNTER: AtomicU32 = AtomicU32::new(0);
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// How IDs are allocated for people added to the database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IdStrategy {
+    /// One more than the highest numeric ID already in use
+    #[default]
+    Sequential,
+    /// A random UUID (v4)
+    Uuid,
+}
+
+impl IdStrategy {
+    /// The strategy that allocated the IDs in `people`: UUIDs if everyone has one, otherwise
+    /// sequential numbers.
+    pub fn used_by(people: &[Person]) -> IdStrategy {
+        if !people.is_empty() && people.iter().all(|p| uuid::Uuid::parse_str(&p.id).is_ok()) {
+            IdStrategy::Uuid
+        } else {
+            IdStrategy::Sequential
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Person {
-    #[serde(skip_deserializing)]
-    pub id: u32,
+    /// Stable identifier, persisted in the `id` column. Empty until one is allocated.
+    #[serde(default)]
+    pub id: String,
     pub first_name: String,
     pub last_name: String,
+    /// May be partial or unknown; see `BirthDate`
     #[serde(with = "date_format")]
-    pub date_of_birth: NaiveDate,
-    pub favorite_sport: Sport,
+    pub date_of_birth: BirthDate,
+    /// Favorite sports, the primary one first. Older files with a single `favorite_sport`
+    /// are read as a list of one.
+    #[serde(default, alias = "favorite_sport", deserialize_with = "sport_list::deserialize")]
+    pub favorite_sports: Vec<Sport>,
+    /// Email addresses, the preferred one first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<String>,
+    /// Phone numbers in E.164 form, e.g. `+15551234567`, the preferred one first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phones: Vec<String>,
+    /// Postal address as free text, which may span several lines
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub address: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Labels such as `coach` or `u12`, in lower case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Values of the database's custom fields (see `FieldSchema`), by field name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
+    /// CSV columns that are neither built in nor custom fields, such as ones added in a
+    /// spreadsheet, as (header, value) in file order. Written back unchanged on save.
+    #[serde(skip)]
+    pub extra_columns: Vec<(String, String)>,
+}
+
+/// An exact age, as returned by `Person::precise_age_on`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Age {
+    pub years: u32,
+    pub months: u32,
+    pub days: u32,
+}
+
+impl fmt::Display for Age {
+    /// Whole years from age 2; younger children also get months (and days under a year),
+    /// e.g. `1y 4m` or `3m 12d`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.years, self.months) {
+            (0, 0) => write!(f, "{}d", self.days),
+            (0, months) => write!(f, "{}m {}d", months, self.days),
+            (1, months) => write!(f, "1y {}m", months),
+            (years, _) => write!(f, "{}", years),
+        }
+    }
+}
+
+/// Today's date in the local time zone.
+pub fn today() -> NaiveDate {
+    Local::now().naive_local().date()
 }
 
 mod date_format {
-    use chrono::NaiveDate;
+    use crate::birth_date::BirthDate;
     use serde::{self, Deserialize, Deserializer, Serializer};
 
-    const FORMAT: &str = "%Y-%m-%d";
-
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BirthDate, D::Error>
     where
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
+        BirthDate::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid date '{}', expected YYYY-MM-DD, YYYY, --MM-DD or nothing",
+                s
+            ))
+        })
     }
 
-    pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
+    pub fn serialize<S>(date: &BirthDate, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let s = date.format(FORMAT).to_string();
-        serializer.serialize_str(&s)
+        serializer.serialize_str(&date.to_string())
+    }
+}
+
+// Reads favorite sports as a list, or as the single sport written before lists were supported
+mod sport_list {
+    use crate::constants::Sport;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Sport),
+        Many(Vec<Sport>),
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Sport>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(sport) => vec![sport],
+            OneOrMany::Many(sports) => sports,
+        })
     }
 }
 
@@ -50,79 +155,383 @@ impl Person {
     pub fn new(
         first_name: String,
         last_name: String,
-        date_of_birth: NaiveDate,
-        favorite_sport: Sport,
+        date_of_birth: BirthDate,
+        favorite_sports: Vec<Sport>,
     ) -> Self {
         Person {
-            id: COUNTER.fetch_add(1, Ordering::Relaxed),
+            id: String::new(),
             first_name,
             last_name,
             date_of_birth,
-            favorite_sport,
+            favorite_sports,
+            emails: Vec::new(),
+            phones: Vec::new(),
+            address: String::new(),
+            notes: String::new(),
+            tags: Vec::new(),
+            custom: BTreeMap::new(),
+            extra_columns: Vec::new(),
         }
     }
 
     pub fn with_id(
-        id: u32,
+        id: String,
         first_name: String,
         last_name: String,
-        date_of_birth: NaiveDate,
-        favorite_sport: Sport,
+        date_of_birth: BirthDate,
+        favorite_sports: Vec<Sport>,
     ) -> Self {
         Person {
             id,
             first_name,
             last_name,
             date_of_birth,
-            favorite_sport,
+            favorite_sports,
+            emails: Vec::new(),
+            phones: Vec::new(),
+            address: String::new(),
+            notes: String::new(),
+            tags: Vec::new(),
+            custom: BTreeMap::new(),
+            extra_columns: Vec::new(),
+        }
+    }
+
+    /// Age in whole years on `date`. A birthday counts from the day itself; people born on
+    /// 29 February have their birthday on 28 February in other years. `None` if the full
+    /// date of birth is not known or `date` is before it.
+    pub fn age_on(&self, date: NaiveDate) -> Option<u32> {
+        self.precise_age_on(date).map(|age| age.years)
+    }
+
+    /// Age on `date` in years, months and days, if the full date of birth is known.
+    pub fn precise_age_on(&self, date: NaiveDate) -> Option<Age> {
+        let dob = self.date_of_birth.full()?;
+        if date < dob {
+            return None;
+        }
+        let mut months = (date.year() - dob.year()) * 12 + date.month() as i32 - dob.month() as i32;
+        // Adding months clamps to the end of shorter months, e.g. 31 January + 1 month is
+        // 28 or 29 February
+        let add_months = |months: i32| dob.checked_add_months(Months::new(months as u32));
+        let mut anniversary = add_months(months);
+        while months > 0 && anniversary.is_none_or(|a| a > date) {
+            months -= 1;
+            anniversary = add_months(months);
+        }
+        let days = anniversary.map_or(0, |a| (date - a).num_days());
+        Some(Age {
+            years: (months / 12) as u32,
+            months: (months % 12) as u32,
+            days: days as u32,
+        })
+    }
+
+    /// Age on `date` for display: exact when the full date of birth is known, a range such
+    /// as `24-25` when only the year is, `-` before birth and `?` when unknown.
+    pub fn age_display_on(&self, date: NaiveDate) -> String {
+        match self.date_of_birth {
+            BirthDate::Full(dob) if date < dob => "-".to_string(),
+            BirthDate::Full(_) => self.precise_age_on(date).map_or_else(String::new, |a| a.to_string()),
+            BirthDate::Year(year) if date.year() < year => "-".to_string(),
+            BirthDate::Year(year) if date.year() == year => "0".to_string(),
+            BirthDate::Year(year) => format!("{}-{}", date.year() - year - 1, date.year() - year),
+            BirthDate::MonthDay { .. } | BirthDate::Unknown => "?".to_string(),
+        }
+    }
+
+    /// Whether the person has `tag`, which must already be in lower case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Adds `tag` unless the person already has it. Returns whether it was added.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.has_tag(tag) {
+            return false;
         }
+        self.tags.push(tag.to_string());
+        true
     }
 
-    pub fn get_age(&self) -> u32 {
-        let today = Local::now().naive_local().date();
-        let age = today.signed_duration_since(self.date_of_birth).num_days() / 365;
-        age as u32
+    /// Removes `tag` if the person has it. Returns whether it was removed.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let before = self.tags.len();
+        self.tags.retain(|t| t != tag);
+        self.tags.len() != before
+    }
+
+    /// The first of the favorite sports, if there are any.
+    pub fn primary_sport(&self) -> Option<&Sport> {
+        self.favorite_sports.first()
     }
 
     pub fn get_favorite_sport_emoji(&self) -> &str {
-        self.favorite_sport.emoji()
+        self.primary_sport().map_or("", |s| s.emoji())
+    }
+
+    /// All the favorite sports in order, separated by commas.
+    pub fn sports_list(&self) -> String {
+        self.favorite_sports.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// The primary sport with its emoji, and how many others there are, e.g. `⚽ Soccer +2`.
+    pub fn sports_summary(&self) -> String {
+        match self.primary_sport() {
+            Some(sport) if self.favorite_sports.len() > 1 => {
+                format!("{} {} +{}", sport.emoji(), sport, self.favorite_sports.len() - 1)
+            }
+            Some(sport) => format!("{} {}", sport.emoji(), sport),
+            None => String::new(),
+        }
     }
 
     /// Reads all `Person` records from a CSV file. Returns a vector of `Person` records.
+    ///
+    /// Files using an older schema version are migrated in memory; they are upgraded on disk
+    /// the next time they are saved. IDs are taken from the `id` column. Rows without one get
+    /// a new sequential ID; duplicate IDs are an error, as is any row that cannot be read.
     pub fn read_from_csv<P: AsRef<Path>>(path: P) -> Result<Vec<Person>, Box<dyn Error>> {
-        let file = File::open(&path)?; // Open the file. Errors returned immediately.
-        let mut reader = csv::Reader::from_reader(file);
-        let mut people = Vec::new();
+        let (people, problems) = Person::read_from_csv_lenient(&path)?;
+        if let Some(problem) = problems.first() {
+            return Err(format!("{}: {}", path.as_ref().display(), problem).into());
+        }
+        Ok(people)
+    }
+
+    /// Reads a CSV file like `read_from_csv`, but skips rows that cannot be read instead of
+    /// failing. Each skipped row is returned as a problem with its line, the column at fault
+    /// when known, and its raw fields so it can be quarantined.
+    pub fn read_from_csv_lenient<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        let (mut people, problems) = Person::read_from_csv_keeping_ids(path)?;
+        fill_missing_ids(&mut people);
+        Ok((people, problems))
+    }
+
+    /// Reads a CSV file like `read_from_csv_lenient`, but leaves the rows without an ID
+    /// without one, so that importing them gives them an ID in the other database.
+    pub fn read_from_csv_keeping_ids<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        let table = read_csv_table(&path)?;
+        let schema = FieldSchema::load_for(&path)?;
+        // Columns holding custom fields
+        let custom_columns: Vec<(usize, &FieldDefinition)> = table
+            .headers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, h)| schema.get(h).map(|field| (i, field)))
+            .collect();
+        // Columns this version does not know, kept so they survive a save. Spreadsheet apps
+        // may pad rows with unnamed columns, which are left out.
+        let extra_columns: Vec<(usize, &String)> = table
+            .headers
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| !h.is_empty() && !CSV_HEADERS.contains(&h.as_str()) && schema.get(h).is_none())
+            .collect();
+        // List columns are split below; serde would read a list as the rest of the record
+        let headers: csv::StringRecord = table
+            .headers
+            .iter()
+            .map(|h| if CSV_LIST_HEADERS.contains(&h.as_str()) { "" } else { h.as_str() })
+            .collect();
+        let list_column = |name: &str| table.headers.iter().position(|h| h == name);
+        let (emails_column, phones_column, tags_column) =
+            (list_column("emails"), list_column("phones"), list_column("tags"));
+        let sports_column = list_column("favorite_sports");
+        let mut people: Vec<Person> = Vec::new();
+        let mut problems = Vec::new();
+        // The line and fields each person was read from
+        let mut sources: Vec<&(u64, Vec<String>)> = Vec::new();
 
         // Iterate for each record in the CSV file.
-        for result in reader.deserialize() {
+        let problem = |(line, row): &(u64, Vec<String>), column: Option<&str>, reason: String| LoadProblem {
+            line: Some(*line),
+            column: column.map(|c| c.to_string()),
+            reason,
+            record: Some(row.clone()),
+        };
+        for source in &table.rows {
+            let (_, row) = source;
+            let problem = |column: Option<&str>, reason: String| problem(source, column, reason);
+            if row.len() != headers.len() {
+                problems.push(problem(
+                    None,
+                    format!("expected {} fields, found {}", headers.len(), row.len()),
+                ));
+                continue;
+            }
+
             // Deserialize the record into a `Person` struct.
-            let mut person: Person = result?;
-            // Assign a unique ID since it's skipped during deserialization
-            person.id = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let record = csv::StringRecord::from(row.clone());
+            let mut person: Person = match record.deserialize(Some(&headers)) {
+                Ok(person) => person,
+                Err(e) => {
+                    let (field, reason) = match e.kind() {
+                        csv::ErrorKind::Deserialize { err, .. } => {
+                            (err.field().map(|i| i as usize), err.kind().to_string())
+                        }
+                        _ => (None, e.to_string()),
+                    };
+                    // Errors raised by `date_format` do not carry the field they came from
+                    let field = field.or_else(|| {
+                        table.headers.iter().position(|h| h == "date_of_birth")
+                            .filter(|&i| BirthDate::parse(&row[i]).is_none())
+                    });
+                    let column = field.and_then(|i| table.headers.get(i));
+                    problems.push(problem(column.map(|c| c.as_str()), reason));
+                    continue;
+                }
+            };
+            person.id = person.id.trim().to_string();
+            person.emails = emails_column.map(|i| split_list(&row[i])).unwrap_or_default();
+            person.phones = phones_column.map(|i| split_list(&row[i])).unwrap_or_default();
+            person.tags = tags_column.map(|i| split_list(&row[i])).unwrap_or_default();
+            person.favorite_sports = sports_column
+                .map(|i| split_list(&row[i]).iter().map(|s| Sport::from_string(s)).collect())
+                .unwrap_or_default();
+            let custom: Result<Vec<(String, String)>, LoadProblem> = custom_columns
+                .iter()
+                .map(|(i, field)| {
+                    field
+                        .parse_value(&row[*i])
+                        .map(|value| (field.name.clone(), value))
+                        .map_err(|reason| problem(Some(&field.name), reason))
+                })
+                .collect();
+            match custom {
+                Ok(values) => person.custom = values.into_iter().filter(|(_, v)| !v.is_empty()).collect(),
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            }
+            person.extra_columns = extra_columns
+                .iter()
+                .map(|(i, header)| ((*header).clone(), row[*i].clone()))
+                .collect();
 
             // Add the `Person` struct to the vector.
             people.push(person);
+            sources.push(source);
+        }
+        for i in duplicate_ids(&people).into_iter().rev() {
+            let person = people.remove(i);
+            problems.push(problem(sources.remove(i), Some("id"), format!("duplicate ID '{}'", person.id)));
         }
+        problems.sort_by_key(|p| p.line);
 
         log::info!("Read {} {} from CSV file: {}", 
             people.len(), 
             if people.len() == 1 {"person" } else { "people" },
             path.as_ref().display()
         );
-        Ok(people)
+        for problem in &problems {
+            log::warn!("Skipped row in {}: {}", path.as_ref().display(), problem);
+        }
+        Ok((people, problems))
     }
 
-    /// Writes all `Person` records to a CSV file.
-    pub fn write_to_csv<P: AsRef<Path>>(path: P, people: &[Person]) -> Result<(), Box<dyn Error>> {
-        let file = File::create(&path)?;
-        let mut writer = csv::Writer::from_writer(file);
+    /// Writes the rows skipped while reading the CSV file at `source` to a new CSV file,
+    /// with the same (current schema) headers, so they can be fixed and imported later.
+    pub fn write_quarantine_csv<P: AsRef<Path>, Q: AsRef<Path>>(
+        source: P,
+        problems: &[LoadProblem],
+        destination: Q,
+    ) -> Result<(), Box<dyn Error>> {
+        let table = read_csv_table(&source)?;
+        write_atomically(&destination, |file| {
+            writeln!(file, "{}", metadata_line())?;
+            // Skipped rows may have any number of fields
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_writer(file);
+            writer.write_record(&table.headers)?;
+            for record in problems.iter().filter_map(|p| p.record.as_ref()) {
+                writer.write_record(record)?;
+            }
+            writer.flush()?;
+            Ok(())
+        })?;
+        log::info!("Wrote {} skipped {} to {}",
+            problems.len(),
+            if problems.len() == 1 { "row" } else { "rows" },
+            destination.as_ref().display()
+        );
+        Ok(())
+    }
 
-        for person in people {
-            writer.serialize(person)?;
+    /// Returns the migrations that loading the CSV file at `path` would apply.
+    pub fn pending_csv_migrations<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Box<dyn Error>> {
+        let content = fs::read_to_string(&path)?;
+        Ok(CsvTable::parse(&content)?.pending_migrations())
+    }
+
+    /// Writes all `Person` records to a CSV file, starting with the schema metadata line.
+    /// Custom fields follow the built-in columns, in the order of the database's field schema,
+    /// and then any other columns the people were read with, in their original order.
+    ///
+    /// The file is replaced atomically, so a failed save leaves the previous content intact.
+    pub fn write_to_csv<P: AsRef<Path>>(path: P, people: &[Person]) -> Result<(), Box<dyn Error>> {
+        let schema = FieldSchema::load_for(&path)?;
+        let mut custom_columns: Vec<String> = schema.fields.iter().map(|f| f.name.clone()).collect();
+        // Keep values of fields the file's schema does not know, e.g. when exporting
+        let mut others: Vec<&String> = people
+            .iter()
+            .flat_map(|p| p.custom.keys())
+            .filter(|k| !custom_columns.contains(k))
+            .collect();
+        others.sort();
+        others.dedup();
+        custom_columns.extend(others.into_iter().cloned());
+        let mut extra_columns: Vec<&String> = Vec::new();
+        for (header, _) in people.iter().flat_map(|p| &p.extra_columns) {
+            if !extra_columns.contains(&header) && !custom_columns.contains(header) {
+                extra_columns.push(header);
+            }
         }
 
-        writer.flush()?;
+        write_atomically(&path, |file| {
+            writeln!(file, "{}", metadata_line())?;
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(file);
+            writer.write_record(
+                CSV_HEADERS
+                    .iter()
+                    .copied()
+                    .chain(custom_columns.iter().map(|c| c.as_str()))
+                    .chain(extra_columns.iter().map(|c| c.as_str())),
+            )?;
+
+            for person in people {
+                let mut record = vec![
+                    person.id.clone(),
+                    person.first_name.clone(),
+                    person.last_name.clone(),
+                    person.date_of_birth.to_string(),
+                    person.favorite_sports.iter().map(|s| s.canonical()).collect::<Vec<_>>().join("; "),
+                    person.emails.join("; "),
+                    person.phones.join("; "),
+                    person.address.clone(),
+                    person.notes.clone(),
+                    person.tags.join("; "),
+                ];
+                for column in &custom_columns {
+                    record.push(person.custom.get(column).cloned().unwrap_or_default());
+                }
+                for column in &extra_columns {
+                    let value = person.extra_columns.iter().find(|(h, _)| h == *column).map(|(_, v)| v.clone());
+                    record.push(value.unwrap_or_default());
+                }
+                writer.write_record(&record)?;
+            }
+
+            writer.flush()?;
+            Ok(())
+        })?;
         log::info!("Wrote {} {} to CSV file: {}", 
             people.len(), 
             if people.len() == 1 {"person" } else { "people" },
@@ -132,21 +541,132 @@ impl Person {
     }
 }
 
+// Splits a list column into its items, e.g. `a@example.com; b@example.com`
+fn split_list(cell: &str) -> Vec<String> {
+    cell.split(';')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// Reads a CSV file into a table, upgraded to the current schema version
+fn read_csv_table<P: AsRef<Path>>(path: P) -> Result<CsvTable, Box<dyn Error>> {
+    let content = fs::read_to_string(&path)?; // Read the file. Errors returned immediately.
+    let mut table = CsvTable::parse(&content)?;
+    for migration in table.migrate() {
+        log::info!("Migrating {}: {}", path.as_ref().display(), migration);
+    }
+    Ok(table)
+}
+
 impl fmt::Display for Person {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{:<15} {:<15} {:<3} {} {:<16}",
+            "{:<15} {:<15} {:<3} {:<16}",
             self.first_name,
             self.last_name,
-            self.get_age(),
-            self.get_favorite_sport_emoji(),
-            self.favorite_sport,
+            self.age_display_on(today()),
+            self.sports_summary(),
         )
     }
 }
 
-pub fn add_person(people: &mut Vec<Person>, person: Person) -> Result<(), Box<dyn Error>> {
+/// Returns a new ID that is not used by anyone in `people`.
+pub fn next_id(people: &[Person], strategy: IdStrategy) -> String {
+    match strategy {
+        IdStrategy::Sequential => next_sequential_id(people).to_string(),
+        IdStrategy::Uuid => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+// The number after the highest numeric ID in `people`
+fn next_sequential_id(people: &[Person]) -> u64 {
+    people
+        .iter()
+        .filter_map(|p| p.id.parse::<u64>().ok())
+        .max()
+        .map_or(0, |max| max + 1)
+}
+
+/// Returns the positions of the people whose ID was already used by someone before them in
+/// `people`. People without an ID are not duplicates; `fill_missing_ids` gives them one.
+pub fn duplicate_ids(people: &[Person]) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    (0..people.len())
+        .filter(|&i| !people[i].id.is_empty() && !seen.insert(people[i].id.as_str()))
+        .collect()
+}
+
+/// Gives everyone in `people` who has no ID a new sequential one.
+pub fn fill_missing_ids(people: &mut [Person]) {
+    let next = next_sequential_id(people);
+    for (person, id) in people.iter_mut().filter(|p| p.id.is_empty()).zip(next..) {
+        person.id = id.to_string();
+    }
+}
+
+/// Returns the index of the person with the given ID.
+pub fn find_person_by_id(people: &[Person], id: &str) -> Option<usize> {
+    people.iter().position(|p| p.id == id.trim())
+}
+
+/// Every tag in use, with how many people have it.
+pub fn tag_counts(people: &[Person]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tag in people.iter().flat_map(|p| &p.tags) {
+        *counts.entry(tag.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+// Trims the names and checks every field before a person is stored, in place of `previous`
+// if it is an edit
+fn validated(mut person: Person, previous: Option<&Person>) -> Result<Person, ValidationErrors> {
+    person.first_name = person.first_name.trim().to_string();
+    person.last_name = person.last_name.trim().to_string();
+    person.emails = person.emails.iter().map(|e| e.trim().to_string()).filter(|e| !e.is_empty()).collect();
+    // Invalid numbers are kept as they are and reported by `validate_change`
+    person.phones = person
+        .phones
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| parse_phone(p).unwrap_or_else(|_| p.trim().to_string()))
+        .collect();
+    person.address = person.address.trim().to_string();
+    person.notes = person.notes.trim().to_string();
+    let mut tags: Vec<String> = Vec::new();
+    for tag in &person.tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    person.tags = tags;
+    let mut sports: Vec<Sport> = Vec::new();
+    for sport in &person.favorite_sports {
+        if !sports.contains(sport) {
+            sports.push(sport.clone());
+        }
+    }
+    person.favorite_sports = sports;
+    validate_change(&person, previous)?;
+    Ok(person)
+}
+
+/// Adds a person, allocating an ID with `strategy` if the person does not have one yet.
+/// Fails with `ValidationErrors` if any field is invalid.
+pub fn add_person(
+    people: &mut Vec<Person>,
+    person: Person,
+    strategy: IdStrategy,
+) -> Result<(), Box<dyn Error>> {
+    let mut person = validated(person, None)?;
+    if person.id.is_empty() {
+        person.id = next_id(people, strategy);
+    } else if find_person_by_id(people, &person.id).is_some() {
+        return Err(format!("Duplicate ID: {}", person.id).into());
+    }
     people.push(person);
     Ok(())
 }
@@ -160,54 +680,348 @@ pub fn delete_person(people: &mut Vec<Person>, index: usize) -> Result<(), Box<d
     }
 }
 
+/// Replaces the person at `index`. Fails with `ValidationErrors` if any field is invalid.
 pub fn edit_person(
     people: &mut Vec<Person>,
     index: usize,
     person: Person,
 ) -> Result<(), Box<dyn Error>> {
     if index < people.len() {
-        people[index] = person;
+        people[index] = validated(person, Some(&people[index]))?;
         Ok(())
     } else {
         Err(format!("Index out of bounds: {}", index).into())
     }
 }
 
+/// Adds `imported` people to `people`, replacing anyone who has the same ID.
+/// People without an ID get a new sequential one, which is also set in `imported`.
+/// Returns how many people were added and how many were updated.
+pub fn import_people(people: &mut Vec<Person>, imported: &mut [Person]) -> (usize, usize) {
+    let mut added = 0;
+    let mut updated = 0;
+    // New IDs follow the imported ones too, so that a later imported person cannot replace
+    // someone who was just given the same ID
+    let mut next = next_sequential_id(people).max(next_sequential_id(imported));
+    let mut index_of: HashMap<String, usize> =
+        people.iter().enumerate().map(|(i, p)| (p.id.clone(), i)).collect();
+    for person in imported {
+        if person.id.is_empty() {
+            person.id = next.to_string();
+            next += 1;
+        }
+        match index_of.get(person.id.trim()) {
+            Some(&index) => {
+                people[index] = person.clone();
+                updated += 1;
+            }
+            None => {
+                index_of.insert(person.id.clone(), people.len());
+                people.push(person.clone());
+                added += 1;
+            }
+        }
+    }
+    (added, updated)
+}
+
+/// Result of merging in-memory edits with changes saved to the same file by someone else.
+pub struct MergeOutcome {
+    pub people: Vec<Person>,
+    /// Human-readable notes about records that were changed on both sides.
+    pub conflicts: Vec<String>,
+}
+
+/// Three-way merge, by ID, of `ours` (in memory) and `theirs` (on disk), which both
+/// started out as `base`. Records changed on both sides keep the local version; records
+/// deleted on one side and changed on the other are kept.
+pub fn merge_people(base: &[Person], ours: &[Person], theirs: &[Person]) -> MergeOutcome {
+    let find = |list: &[Person], id: &str| list.iter().find(|p| p.id == id).cloned();
+    let mut people = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut clashing = Vec::new();
+
+    // Walk the on-disk version first so its order is kept
+    for their in theirs {
+        match (find(base, &their.id), find(ours, &their.id)) {
+            (Some(base_person), Some(our)) => {
+                if our == base_person || our == *their {
+                    people.push(their.clone());
+                } else if *their == base_person {
+                    people.push(our);
+                } else {
+                    conflicts.push(format!("ID {}: changed in both versions, kept yours", our.id));
+                    people.push(our);
+                }
+            }
+            (Some(base_person), None) => {
+                // Deleted locally; keep it only if it was changed on disk meanwhile
+                if *their != base_person {
+                    conflicts.push(format!(
+                        "ID {}: you deleted a person who was changed on disk, kept theirs",
+                        their.id
+                    ));
+                    people.push(their.clone());
+                }
+            }
+            (None, Some(our)) => {
+                // Added on both sides with the same ID
+                people.push(their.clone());
+                if our != *their {
+                    clashing.push(our);
+                }
+            }
+            (None, None) => people.push(their.clone()),
+        }
+    }
+
+    for our in ours {
+        if find(theirs, &our.id).is_some() {
+            continue;
+        }
+        match find(base, &our.id) {
+            None => people.push(our.clone()),
+            Some(base_person) => {
+                // Deleted on disk; keep it only if it was changed locally
+                if *our != base_person {
+                    conflicts.push(format!(
+                        "ID {}: deleted on disk but changed by you, kept yours",
+                        our.id
+                    ));
+                    people.push(our.clone());
+                }
+            }
+        }
+    }
+
+    for mut our in clashing {
+        let old_id = our.id.clone();
+        our.id = next_id(&people, IdStrategy::Sequential);
+        conflicts.push(format!(
+            "ID {}: added in both versions, yours now has ID {}",
+            old_id, our.id
+        ));
+        people.push(our);
+    }
+
+    MergeOutcome { people, conflicts }
+}
+
 #[derive(Tabled)]
 pub struct PersonTableRow {
     pub idx: String,
+    pub id: String,
     pub first_name: String,
     pub last_name: String,
     pub age: String,
-    pub favorite_sport: String,
+    pub favorite_sports: String,
+    pub tags: String,
+}
+
+/// Prints the people `include` accepts as a table, with ages as of `as_of` and a column for
+/// each custom field. The index column keeps each person's position in `people`.
+pub fn print_people(people: &[Person], as_of: NaiveDate, schema: &FieldSchema, include: &dyn Fn(&Person) -> bool) {
+    let indices: Vec<usize> = (0..people.len()).filter(|&i| include(&people[i])).collect();
+    print_people_at(people, &indices, as_of, schema);
 }
 
-pub fn print_people(people: &[Person]) {
-    let mut rows: Vec<PersonTableRow> = Vec::new();
-    for (idx, p) in people.iter().enumerate() {
+/// Prints the people at `indices` in `people` as a table, in that order.
+pub fn print_people_at(people: &[Person], indices: &[usize], as_of: NaiveDate, schema: &FieldSchema) {
+    let mut builder = Builder::default();
+    builder.push_record(
+        PersonTableRow::headers()
+            .into_iter()
+            .map(|h| h.into_owned())
+            .chain(schema.fields.iter().map(|f| f.label().to_string())),
+    );
+    for &idx in indices {
+        let p = &people[idx];
         let idx_str = idx.to_string();
         let first_name = p.first_name.clone();
         let last_name = p.last_name.clone();
-        let age = p.get_age().to_string();
-        let favorite_sport = format!("{} {}", p.favorite_sport.emoji(), p.favorite_sport);
-        rows.push(PersonTableRow {
+        let age = p.age_display_on(as_of);
+        let favorite_sports = p.sports_summary();
+        let row = PersonTableRow {
             idx: idx_str,
+            id: p.id.clone(),
             first_name,
             last_name,
             age,
-            favorite_sport,
-        });
+            favorite_sports,
+            tags: p.tags.join(", "),
+        };
+        builder.push_record(
+            row.fields()
+                .into_iter()
+                .map(|f| f.into_owned())
+                .chain(schema.fields.iter().map(|f| p.custom.get(&f.name).cloned().unwrap_or_default())),
+        );
     }
-    let mut base_table = Table::new(rows);
+    let mut base_table = builder.build();
     let table = base_table.with(tabled::settings::Style::rounded());
     println!("{}", table);
 }
 
 /// Creates a new CSV file for people with the correct headers.
 pub fn create_new_csv_file<P: AsRef<std::path::Path>>(path: P) -> Result<(), Box<dyn std::error::Error>> {
-    let mut writer = csv::Writer::from_path(&path)?;
-    writer.write_record(CSV_HEADERS)?;
-    writer.flush()?;
+    write_atomically(&path, |file| {
+        writeln!(file, "{}", metadata_line())?;
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(CSV_HEADERS)?;
+        writer.flush()?;
+        Ok(())
+    })?;
     log::info!("Created new CSV file: {:}", path.as_ref().display());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(id: &str) -> Person {
+        Person::with_id(id.to_string(), "Ann".to_string(), "Miller".to_string(), BirthDate::Unknown, Vec::new())
+    }
+
+    fn ids(people: &[Person]) -> Vec<&str> {
+        people.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn missing_ids_follow_the_highest_numeric_id() {
+        let mut people = vec![person(""), person("7"), person("abc"), person(""), person("3")];
+        fill_missing_ids(&mut people);
+        assert_eq!(ids(&people), ["8", "7", "abc", "9", "3"]);
+
+        let mut people = vec![person(""), person("")];
+        fill_missing_ids(&mut people);
+        assert_eq!(ids(&people), ["0", "1"]);
+    }
+
+    #[test]
+    fn import_replaces_by_id_and_numbers_the_rest() {
+        let mut people = vec![person("0"), person("4")];
+        let mut imported = vec![person("4"), person(""), person("9"), person("")];
+        assert_eq!(import_people(&mut people, &mut imported), (3, 1));
+        assert_eq!(ids(&imported), ["4", "10", "9", "11"]);
+        assert_eq!(ids(&people), ["0", "4", "10", "9", "11"]);
+    }
+
+    #[test]
+    fn new_ids_do_not_clash_with_ids_later_in_the_import() {
+        let mut people = vec![person("0"), person("4")];
+        let mut imported = vec![person(""), person("5")];
+        assert_eq!(import_people(&mut people, &mut imported), (2, 0));
+        assert_eq!(ids(&people), ["0", "4", "6", "5"]);
+    }
+
+    #[test]
+    fn new_ids_follow_the_kind_already_in_use() {
+        let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        assert_eq!(IdStrategy::used_by(&[person(uuid)]), IdStrategy::Uuid);
+        assert_eq!(IdStrategy::used_by(&[person(uuid), person("3")]), IdStrategy::Sequential);
+        assert_eq!(IdStrategy::used_by(&[]), IdStrategy::Sequential);
+    }
+
+    fn born(year: i32, month: u32, day: u32) -> Person {
+        let mut person = person("0");
+        person.date_of_birth = BirthDate::Full(date(year, month, day));
+        person
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn birthday_counts_from_the_day_itself() {
+        let ann = born(2000, 6, 15);
+        assert_eq!(ann.age_on(date(2024, 6, 14)), Some(23));
+        assert_eq!(ann.age_on(date(2024, 6, 15)), Some(24));
+        assert_eq!(ann.age_on(date(2000, 6, 15)), Some(0));
+        assert_eq!(ann.age_on(date(2000, 6, 14)), None);
+    }
+
+    #[test]
+    fn leap_day_birthday_is_28_february_in_other_years() {
+        let ann = born(2000, 2, 29);
+        assert_eq!(ann.age_on(date(2023, 2, 27)), Some(22));
+        assert_eq!(ann.age_on(date(2023, 2, 28)), Some(23));
+        assert_eq!(ann.age_on(date(2024, 2, 28)), Some(23));
+        assert_eq!(ann.age_on(date(2024, 2, 29)), Some(24));
+    }
+
+    #[test]
+    fn months_are_clamped_to_the_end_of_shorter_months() {
+        let ann = born(2023, 1, 31);
+        assert_eq!(ann.precise_age_on(date(2023, 2, 27)), Some(Age { years: 0, months: 0, days: 27 }));
+        assert_eq!(ann.precise_age_on(date(2023, 2, 28)), Some(Age { years: 0, months: 1, days: 0 }));
+        assert_eq!(born(2024, 1, 31).precise_age_on(date(2024, 2, 29)), Some(Age { years: 0, months: 1, days: 0 }));
+    }
+
+    #[test]
+    fn infants_get_months_and_days() {
+        let baby = born(2024, 3, 10);
+        assert_eq!(baby.precise_age_on(date(2024, 6, 15)), Some(Age { years: 0, months: 3, days: 5 }));
+        assert_eq!(baby.precise_age_on(date(2024, 6, 5)), Some(Age { years: 0, months: 2, days: 26 }));
+        assert_eq!(baby.age_display_on(date(2024, 6, 15)), "3m 5d");
+        assert_eq!(baby.age_display_on(date(2024, 3, 12)), "2d");
+        assert_eq!(baby.age_display_on(date(2025, 7, 15)), "1y 4m");
+        assert_eq!(baby.age_display_on(date(2026, 3, 10)), "2");
+    }
+
+    #[test]
+    fn age_display_of_partial_dates_and_dates_before_birth() {
+        let mut ann = person("0");
+        ann.date_of_birth = BirthDate::Year(1999);
+        assert_eq!(ann.age_display_on(date(2024, 6, 15)), "24-25");
+        assert_eq!(ann.age_display_on(date(1999, 12, 31)), "0");
+        assert_eq!(ann.age_display_on(date(1998, 12, 31)), "-");
+        assert_eq!(born(2000, 6, 15).age_display_on(date(2000, 6, 14)), "-");
+        ann.date_of_birth = BirthDate::MonthDay { month: 6, day: 15 };
+        assert_eq!(ann.age_display_on(date(2024, 6, 15)), "?");
+        ann.date_of_birth = BirthDate::Unknown;
+        assert_eq!(ann.age_display_on(date(2024, 6, 15)), "?");
+        assert_eq!(ann.age_on(date(2024, 6, 15)), None);
+    }
+
+    #[test]
+    fn unknown_columns_survive_a_save() {
+        let dir = std::env::temp_dir().join(format!("extra-columns-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("people.csv");
+        // Written by a newer version, then padded with an unnamed column by a spreadsheet app
+        let header = format!("{},membership,club,", CSV_HEADERS.join(","));
+        fs::write(
+            &path,
+            format!(
+                "{}\n{}\n0,Ann,Miller,,,,,,,,gold,\"Tigers, Under 12\",\n1,Bob,Smith,,,,,,,,,Lions,\n",
+                metadata_line(),
+                header
+            ),
+        )
+        .unwrap();
+        let extras = |people: &[Person]| -> Vec<Vec<(String, String)>> {
+            people.iter().map(|p| p.extra_columns.clone()).collect()
+        };
+        let pair = |h: &str, v: &str| (h.to_string(), v.to_string());
+        let expected = [
+            vec![pair("membership", "gold"), pair("club", "Tigers, Under 12")],
+            vec![pair("membership", ""), pair("club", "Lions")],
+        ];
+
+        let mut people = Person::read_from_csv(&path).unwrap();
+        assert_eq!(extras(&people), expected);
+        people[1].first_name = "Robert".to_string();
+        Person::write_to_csv(&path, &people).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[1], format!("{},membership,club", CSV_HEADERS.join(",")));
+        assert!(lines[2].ends_with(",gold,\"Tigers, Under 12\""), "{}", lines[2]);
+        let people = Person::read_from_csv(&path).unwrap();
+        assert_eq!(people[1].first_name, "Robert");
+        assert_eq!(extras(&people), expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
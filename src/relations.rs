@@ -0,0 +1,320 @@
+use crate::atomic_file::write_atomically;
+use crate::person::{find_person_by_id, Person};
+use crate::store::companion_path;
+use gettextrs::gettext;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const RELATIONS_HEADERS: &[&str] = &["from_id", "kind", "to_id"];
+
+/// How two people are related.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelationKind {
+    Parent,
+    Guardian,
+    Sibling,
+    Spouse,
+    /// Any other relationship, e.g. `coach` or `carpool`
+    Custom(String),
+}
+
+impl RelationKind {
+    /// Parses the name of a kind. Names other than the built-in ones are custom kinds.
+    pub fn parse(s: &str) -> Result<RelationKind, String> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "" => Err("The kind of relationship is missing".to_string()),
+            "parent" => Ok(RelationKind::Parent),
+            "guardian" => Ok(RelationKind::Guardian),
+            "sibling" => Ok(RelationKind::Sibling),
+            "spouse" => Ok(RelationKind::Spouse),
+            _ if s.contains(',') => Err(format!("Invalid relationship '{}': commas are not allowed", s)),
+            _ => Ok(RelationKind::Custom(s.to_string())),
+        }
+    }
+
+    /// Whether the relationship reads the same from both sides.
+    pub fn is_symmetric(&self) -> bool {
+        matches!(self, RelationKind::Sibling | RelationKind::Spouse)
+    }
+
+    /// How the relationship reads from one side: the `from` person is "parent of" the other,
+    /// and the `to` person is "child of" the other.
+    pub fn describe(&self, from_side: bool) -> String {
+        match (self, from_side) {
+            (RelationKind::Parent, true) => gettext("parent of"),
+            (RelationKind::Parent, false) => gettext("child of"),
+            (RelationKind::Guardian, true) => gettext("guardian of"),
+            (RelationKind::Guardian, false) => gettext("ward of"),
+            (RelationKind::Sibling, _) => gettext("sibling of"),
+            (RelationKind::Spouse, _) => gettext("spouse of"),
+            (RelationKind::Custom(name), true) => format!("{} {}", name, gettext("of")),
+            (RelationKind::Custom(name), false) => format!("{} {}", gettext("has"), name),
+        }
+    }
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelationKind::Parent => write!(f, "parent"),
+            RelationKind::Guardian => write!(f, "guardian"),
+            RelationKind::Sibling => write!(f, "sibling"),
+            RelationKind::Spouse => write!(f, "spouse"),
+            RelationKind::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A relationship between two people, by ID: `from_id` is `kind` of `to_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub from_id: String,
+    pub kind: RelationKind,
+    pub to_id: String,
+}
+
+impl Relation {
+    pub fn involves(&self, id: &str) -> bool {
+        self.from_id == id || self.to_id == id
+    }
+
+    // The same relationship, including a symmetric one written the other way round
+    fn same_as(&self, other: &Relation) -> bool {
+        self.kind == other.kind
+            && ((self.from_id == other.from_id && self.to_id == other.to_id)
+                || (self.kind.is_symmetric() && self.from_id == other.to_id && self.to_id == other.from_id))
+    }
+}
+
+/// Path of the file holding the relationships for the database at `database`, for formats
+/// that have no place for them in the database itself.
+pub fn path_for<P: AsRef<Path>>(database: P) -> PathBuf {
+    companion_path(database, ".relations.csv")
+}
+
+/// Reads relationships from a CSV file with `from_id,kind,to_id` columns. A missing file
+/// means there are none.
+pub fn read_relations_csv<P: AsRef<Path>>(path: P) -> Result<Vec<Relation>, Box<dyn Error>> {
+    if !path.as_ref().exists() {
+        return Ok(Vec::new());
+    }
+    let mut reader = csv::Reader::from_path(&path)?;
+    let mut relations = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |p| p.line());
+        let (Some(from_id), Some(kind), Some(to_id)) = (record.get(0), record.get(1), record.get(2)) else {
+            return Err(format!("{}: line {}: expected {} fields", path.as_ref().display(), line, RELATIONS_HEADERS.len()).into());
+        };
+        let kind = RelationKind::parse(kind).map_err(|e| format!("{}: line {}: {}", path.as_ref().display(), line, e))?;
+        relations.push(Relation {
+            from_id: from_id.trim().to_string(),
+            kind,
+            to_id: to_id.trim().to_string(),
+        });
+    }
+    Ok(relations)
+}
+
+/// Writes relationships to a CSV file with `from_id,kind,to_id` columns.
+pub fn write_relations_csv<P: AsRef<Path>>(path: P, relations: &[Relation]) -> Result<(), Box<dyn Error>> {
+    write_atomically(&path, |file| {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(RELATIONS_HEADERS)?;
+        for relation in relations {
+            writer.write_record([relation.from_id.as_str(), &relation.kind.to_string(), relation.to_id.as_str()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    })?;
+    log::info!("Wrote {} {} to {}",
+        relations.len(),
+        if relations.len() == 1 { "relationship" } else { "relationships" },
+        path.as_ref().display()
+    );
+    Ok(())
+}
+
+/// Adds a relationship between two different people in `people`. Fails if either ID is
+/// unknown or the relationship already exists.
+pub fn add_relation(relations: &mut Vec<Relation>, people: &[Person], relation: Relation) -> Result<(), Box<dyn Error>> {
+    for id in [&relation.from_id, &relation.to_id] {
+        find_person_by_id(people, id).ok_or_else(|| format!("No person with ID {}", id))?;
+    }
+    if relation.from_id == relation.to_id {
+        return Err("A person cannot be related to themselves".into());
+    }
+    if relations.iter().any(|r| r.same_as(&relation)) {
+        return Err(format!(
+            "{} is already {} {}",
+            relation.from_id,
+            relation.kind.describe(true),
+            relation.to_id
+        )
+        .into());
+    }
+    relations.push(relation);
+    Ok(())
+}
+
+/// Removes every relationship between the two people, in either direction. Returns how many
+/// were removed.
+pub fn remove_relations_between(relations: &mut Vec<Relation>, a: &str, b: &str) -> usize {
+    let before = relations.len();
+    relations.retain(|r| !(r.involves(a) && r.involves(b)));
+    before - relations.len()
+}
+
+/// Removes the relationships that refer to anyone not in `people`, such as someone who was
+/// deleted. Returns how many were removed.
+pub fn retain_valid(relations: &mut Vec<Relation>, people: &[Person]) -> usize {
+    let before = relations.len();
+    relations.retain(|r| find_person_by_id(people, &r.from_id).is_some() && find_person_by_id(people, &r.to_id).is_some());
+    before - relations.len()
+}
+
+/// The people related to the person with `id`, each with how they relate from that
+/// person's side, e.g. ("parent of", Jose).
+pub fn related_people<'a>(relations: &[Relation], people: &'a [Person], id: &str) -> Vec<(String, &'a Person)> {
+    relations
+        .iter()
+        .filter_map(|r| {
+            let (other, from_side) = if r.from_id == id {
+                (&r.to_id, true)
+            } else if r.to_id == id {
+                (&r.from_id, false)
+            } else {
+                return None;
+            };
+            let person = &people[find_person_by_id(people, other)?];
+            Some((r.kind.describe(from_side), person))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::birth_date::BirthDate;
+    use crate::constants::Sport;
+    use crate::person::{add_person, delete_person, IdStrategy};
+
+    fn person(id: &str, first_name: &str) -> Person {
+        Person::with_id(id.to_string(), first_name.to_string(), "Miller".to_string(), BirthDate::Unknown, vec![Sport::Tennis])
+    }
+
+    fn relation(from_id: &str, kind: RelationKind, to_id: &str) -> Relation {
+        Relation { from_id: from_id.to_string(), kind, to_id: to_id.to_string() }
+    }
+
+    #[test]
+    fn relationships_of_a_deleted_person_do_not_pass_to_a_new_one() {
+        let mut people = vec![person("0", "Ann"), person("1", "Bob")];
+        let mut relations = vec![relation("1", RelationKind::Parent, "0")];
+
+        // Deleting the person with the highest ID frees that ID for the next one added
+        delete_person(&mut people, 1).unwrap();
+        retain_valid(&mut relations, &people);
+        let new = Person::new("Cid".to_string(), "Smith".to_string(), BirthDate::Unknown, vec![Sport::Golf]);
+        let strategy = IdStrategy::used_by(&people);
+        add_person(&mut people, new, strategy).unwrap();
+
+        assert_eq!(people[1].id, "1");
+        assert!(relations.is_empty());
+        assert!(related_people(&relations, &people, "0").is_empty());
+    }
+
+    fn family() -> Vec<Person> {
+        vec![person("0", "Ann"), person("1", "Bob"), person("2", "Cid")]
+    }
+
+    #[test]
+    fn relationships_need_two_different_known_people() {
+        let people = family();
+        let mut relations = Vec::new();
+        add_relation(&mut relations, &people, relation("0", RelationKind::Parent, "1")).unwrap();
+
+        let error = add_relation(&mut relations, &people, relation("0", RelationKind::Parent, "9")).unwrap_err();
+        assert_eq!(error.to_string(), "No person with ID 9");
+        let error = add_relation(&mut relations, &people, relation("1", RelationKind::Sibling, "1")).unwrap_err();
+        assert_eq!(error.to_string(), "A person cannot be related to themselves");
+        assert_eq!(relations.len(), 1);
+    }
+
+    #[test]
+    fn symmetric_relationships_are_the_same_both_ways_round() {
+        let people = family();
+        let mut relations = Vec::new();
+        add_relation(&mut relations, &people, relation("1", RelationKind::Sibling, "2")).unwrap();
+        let error = add_relation(&mut relations, &people, relation("2", RelationKind::Sibling, "1")).unwrap_err();
+        assert_eq!(error.to_string(), "2 is already sibling of 1");
+
+        // Being someone's parent is not the same as being their child
+        add_relation(&mut relations, &people, relation("0", RelationKind::Parent, "1")).unwrap();
+        add_relation(&mut relations, &people, relation("1", RelationKind::Parent, "0")).unwrap();
+        assert!(add_relation(&mut relations, &people, relation("0", RelationKind::Parent, "1")).is_err());
+        assert_eq!(relations.len(), 3);
+    }
+
+    #[test]
+    fn removing_relationships_between_two_people() {
+        let mut relations = vec![
+            relation("0", RelationKind::Parent, "1"),
+            relation("1", RelationKind::Custom("coach".to_string()), "0"),
+            relation("0", RelationKind::Parent, "2"),
+        ];
+        assert_eq!(remove_relations_between(&mut relations, "1", "0"), 2);
+        assert_eq!(relations, [relation("0", RelationKind::Parent, "2")]);
+        assert_eq!(remove_relations_between(&mut relations, "1", "2"), 0);
+    }
+
+    #[test]
+    fn only_relationships_between_people_still_there_are_kept() {
+        let mut people = family();
+        let mut relations = vec![
+            relation("0", RelationKind::Parent, "1"),
+            relation("2", RelationKind::Guardian, "1"),
+            relation("0", RelationKind::Spouse, "7"),
+        ];
+        assert_eq!(retain_valid(&mut relations, &people), 1);
+        people.remove(2);
+        assert_eq!(retain_valid(&mut relations, &people), 1);
+        assert_eq!(relations, [relation("0", RelationKind::Parent, "1")]);
+    }
+
+    #[test]
+    fn related_people_are_described_from_the_side_asked_about() {
+        let people = family();
+        let relations = vec![
+            relation("0", RelationKind::Parent, "1"),
+            relation("2", RelationKind::Sibling, "1"),
+            relation("0", RelationKind::Custom("coach".to_string()), "2"),
+            relation("0", RelationKind::Guardian, "9"),
+        ];
+        let describe = |id: &str| -> Vec<(String, String)> {
+            related_people(&relations, &people, id)
+                .into_iter()
+                .map(|(description, person)| (description, person.first_name.clone()))
+                .collect()
+        };
+        let pair = |description: &str, name: &str| (description.to_string(), name.to_string());
+        // Someone who is not in the database is left out
+        assert_eq!(describe("0"), [pair("parent of", "Bob"), pair("coach of", "Cid")]);
+        assert_eq!(describe("1"), [pair("child of", "Ann"), pair("sibling of", "Cid")]);
+        assert_eq!(describe("2"), [pair("sibling of", "Bob"), pair("has coach", "Ann")]);
+        assert_eq!(RelationKind::Guardian.describe(false), "ward of");
+    }
+
+    #[test]
+    fn kinds_parse_and_read_back() {
+        assert_eq!(RelationKind::parse(" Parent "), Ok(RelationKind::Parent));
+        assert_eq!(RelationKind::parse("Car pool"), Ok(RelationKind::Custom("Car pool".to_string())));
+        assert!(RelationKind::parse(" ").is_err());
+        assert!(RelationKind::parse("coach, mentor").is_err());
+        for kind in [RelationKind::Spouse, RelationKind::Custom("coach".to_string())] {
+            assert_eq!(RelationKind::parse(&kind.to_string()), Ok(kind));
+        }
+    }
+}
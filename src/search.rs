@@ -0,0 +1,92 @@
+use crate::person::Person;
+use crate::phonetic::sound_similarity;
+use crate::text::{edit_distance, name_words};
+
+// Scores of a query word against one word of a name
+const EXACT: u32 = 100;
+const PREFIX: u32 = 80;
+const CONTAINED: u32 = 60;
+const TYPO: u32 = 40;
+const TYPO_IN_PREFIX: u32 = 30;
+// Extra score when the query is the whole name, first or last name first
+const WHOLE_NAME: u32 = 50;
+
+/// Finds the people whose first or last name matches `query` and returns their indices in
+/// `people`, best match first. Case, accents and punctuation are ignored, and every word of
+/// the query must match a word of the name exactly, as its beginning, inside it or with a
+/// typo or two, so `jose` finds `José` and `marinez` finds `Martínez`.
+pub fn search(people: &[Person], query: &str) -> Vec<usize> {
+    rank(people, query, word_score)
+}
+
+/// Finds the people whose first or last name sounds like `query`, going by the Double Metaphone
+/// and Soundex codes of each word, and returns their indices in `people`, best match first. For
+/// names that were only heard, such as `Jon Smyth` for John Smith.
+pub fn search_sounds_like(people: &[Person], query: &str) -> Vec<usize> {
+    rank(people, query, |q, w| sound_similarity(q, w).map(|similarity| similarity * 25))
+}
+
+// The people for whom every word of `query` matches a word of the name, best match first
+fn rank(people: &[Person], query: &str, word_score: fn(&str, &str) -> Option<u32>) -> Vec<usize> {
+    let query = name_words(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut found: Vec<(u32, usize)> = people
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| score(&query, p, word_score).map(|score| (score, i)))
+        .collect();
+    found.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.cmp(a_score).then_with(|| {
+            let (a, b) = (&people[*a], &people[*b]);
+            (&a.last_name, &a.first_name).cmp(&(&b.last_name, &b.first_name))
+        })
+    });
+    found.into_iter().map(|(_, i)| i).collect()
+}
+
+// How well the words of a query match a person's name, if every word matches
+fn score(query: &[String], person: &Person, word_score: fn(&str, &str) -> Option<u32>) -> Option<u32> {
+    let first = name_words(&person.first_name);
+    let last = name_words(&person.last_name);
+    let words: Vec<&String> = first.iter().chain(&last).collect();
+    let mut total = 0;
+    for q in query {
+        total += words.iter().filter_map(|w| word_score(q, w)).max()?;
+    }
+    let first_last: Vec<&String> = first.iter().chain(&last).collect();
+    let last_first: Vec<&String> = last.iter().chain(&first).collect();
+    let query: Vec<&String> = query.iter().collect();
+    if query == first_last || query == last_first {
+        total += WHOLE_NAME;
+    }
+    Some(total)
+}
+
+fn word_score(query: &str, word: &str) -> Option<u32> {
+    if word == query {
+        return Some(EXACT);
+    }
+    if word.starts_with(query) {
+        return Some(PREFIX);
+    }
+    let length = query.chars().count();
+    if length >= 2 && word.contains(query) {
+        return Some(CONTAINED);
+    }
+    // Short words allow no typos, longer ones one and long ones two
+    let max_distance = match length {
+        0..=2 => return None,
+        3..=6 => 1,
+        _ => 2,
+    };
+    let distance = edit_distance(query, word);
+    if distance <= max_distance {
+        return Some(TYPO - 10 * (distance as u32 - 1));
+    }
+    // A typo in the part typed so far, e.g. `mra` for `Martínez`
+    let prefix: String = word.chars().take(length).collect();
+    let distance = edit_distance(query, &prefix);
+    (distance <= max_distance).then(|| TYPO_IN_PREFIX - 10 * (distance as u32 - 1))
+}
@@ -0,0 +1,381 @@
+use crate::atomic_file::write_atomically;
+use crate::text::{edit_distance, fold_diacritics};
+use gettextrs::gettext;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// The built-in sports, compiled into the binary
+const BUILT_IN_CATALOG: &str = include_str!("../data/sports.json");
+
+/// A sport that can be chosen as a favorite.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SportEntry {
+    /// Stable name of the sport, e.g. `water_polo`
+    pub key: String,
+    /// English name, also used to look up a translation in the gettext catalogs
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub emoji: String,
+    /// Other names the sport is known by, e.g. `jogging` for running
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Kind of sport, e.g. `team`, `racket` or `winter`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub category: String,
+    /// Name by language, e.g. `es` or `en_GB`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, String>,
+}
+
+impl SportEntry {
+    /// The name in `language`, e.g. `en_GB`, or failing that its base language, `en`.
+    pub fn translation(&self, language: &str) -> Option<&str> {
+        let base = language.split('_').next().unwrap_or(language);
+        self.translations
+            .get(language)
+            .or_else(|| self.translations.get(base))
+            .map(String::as_str)
+    }
+
+    /// The name in the language of the current locale.
+    pub fn localized_name(&self) -> String {
+        match current_language().as_deref().and_then(|language| self.translation(language)) {
+            Some(name) => name.to_string(),
+            None => gettext(self.name.as_str()),
+        }
+    }
+
+    // Whether `input` is the key, name, an alias or a translated name of the sport
+    fn matches(&self, input: &str, include_translations: bool) -> bool {
+        let input = normalize(input);
+        normalize(&self.key) == input
+            || normalize(&self.name) == input
+            || self.aliases.iter().any(|a| normalize(a) == input)
+            || (include_translations && self.translations.values().any(|t| normalize(t) == input))
+    }
+
+    // Every name the sport is known by
+    fn names(&self) -> impl Iterator<Item = &String> {
+        [&self.key, &self.name]
+            .into_iter()
+            .chain(&self.aliases)
+            .chain(self.translations.values())
+    }
+}
+
+/// The sports known by name, with their emoji. Built-in sports can be extended, or changed by
+/// key, in a `sports.json` file in the user's configuration directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SportCatalog {
+    #[serde(default)]
+    pub sports: Vec<SportEntry>,
+}
+
+static CATALOG: Lazy<SportCatalog> = Lazy::new(|| {
+    SportCatalog::load().unwrap_or_else(|e| {
+        log::warn!("Using the built-in sports only: {}", e);
+        SportCatalog::built_in()
+    })
+});
+
+// Language whose translated names are read first, see `prefer_language`
+static PREFERRED_LANGUAGE: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// Makes names translated into `language` take precedence over everything but keys when
+/// sports are read, e.g. `Football` is soccer for `en_GB`. Used to repair files that were
+/// saved with translated names; `None` goes back to the usual order.
+pub fn prefer_language(language: Option<&str>) {
+    *PREFERRED_LANGUAGE.write().unwrap() = language.map(str::to_string);
+}
+
+/// The catalog in use: the built-in sports and the user's own, read on first use.
+pub fn catalog() -> &'static SportCatalog {
+    &CATALOG
+}
+
+impl SportCatalog {
+    pub fn built_in() -> SportCatalog {
+        serde_json::from_str(BUILT_IN_CATALOG).expect("built-in sport catalog is valid")
+    }
+
+    /// Path of the user's catalog, `people-db/sports.json` in the configuration directory.
+    pub fn user_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(config_dir.join("people-db").join("sports.json"))
+    }
+
+    /// Reads the user's catalog. A missing file is an empty catalog.
+    pub fn load_user() -> Result<SportCatalog, Box<dyn Error>> {
+        match SportCatalog::user_path() {
+            Some(path) => SportCatalog::read(&path),
+            None => Ok(SportCatalog::default()),
+        }
+    }
+
+    // Reads the catalog file at `path`. A missing file is an empty catalog.
+    fn read(path: &Path) -> Result<SportCatalog, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(SportCatalog::default());
+        }
+        let content = fs::read_to_string(path)?;
+        let catalog: SportCatalog =
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        log::info!("Read {} sports from {}", catalog.sports.len(), path.display());
+        Ok(catalog)
+    }
+
+    /// The built-in catalog with the user's catalog applied to it.
+    pub fn load() -> Result<SportCatalog, Box<dyn Error>> {
+        let mut catalog = SportCatalog::built_in();
+        catalog.apply(SportCatalog::load_user()?);
+        Ok(catalog)
+    }
+
+    // Adds the sports of `user`, each replacing the sport with the same key if there is one
+    fn apply(&mut self, user: SportCatalog) {
+        for entry in user.sports {
+            match self.sports.iter_mut().find(|e| e.key == entry.key) {
+                Some(existing) => *existing = entry,
+                None => self.sports.push(entry),
+            }
+        }
+    }
+
+    /// Writes this catalog as the user's catalog.
+    pub fn save_user(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = SportCatalog::user_path().ok_or("Cannot find the configuration directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(&path, |file| {
+            serde_json::to_writer_pretty(&mut *file, self)?;
+            file.write_all(b"\n")?;
+            Ok(())
+        })?;
+        log::info!("Wrote {} sports to {}", self.sports.len(), path.display());
+        Ok(path)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&SportEntry> {
+        self.sports.iter().find(|e| e.key == key)
+    }
+
+    /// Finds a sport by key, name or alias, ignoring case, spaces, hyphens and underscores.
+    /// Translated names are tried last, so that e.g. `football` is not taken for soccer.
+    pub fn find(&self, input: &str) -> Option<&SportEntry> {
+        self.find_preferring(input, PREFERRED_LANGUAGE.read().unwrap().as_deref())
+    }
+
+    // Finds a sport like `find`, with names translated into `language` read first
+    fn find_preferring(&self, input: &str, language: Option<&str>) -> Option<&SportEntry> {
+        if let Some(entry) = self.get(input.trim()) {
+            return Some(entry);
+        }
+        if let Some(language) = language {
+            let input = normalize(input);
+            let translated = self
+                .sports
+                .iter()
+                .find(|e| e.translation(language).is_some_and(|t| normalize(t) == input));
+            if translated.is_some() {
+                return translated;
+            }
+        }
+        self.sports
+            .iter()
+            .find(|e| e.matches(input, false))
+            .or_else(|| self.sports.iter().find(|e| e.matches(input, true)))
+    }
+
+    /// The sport whose key, name, alias or translated name is closest to `input`, if one is
+    /// only a typo away: one edit for short names, two for names of eight letters or more.
+    pub fn suggest(&self, input: &str) -> Option<&SportEntry> {
+        let input = normalize(input);
+        let max_distance = if input.chars().count() >= 8 { 2 } else { 1 };
+        self.sports
+            .iter()
+            .filter_map(|e| {
+                let distance = e.names().map(|name| edit_distance(&input, &normalize(name))).min()?;
+                (distance <= max_distance).then_some((distance, e))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, e)| e)
+    }
+
+    /// Adds a sport. Keys are lower case letters, digits and underscores, and neither the key,
+    /// the name nor an alias may already name another sport in `existing`.
+    pub fn add(&mut self, entry: SportEntry, existing: &SportCatalog) -> Result<(), Box<dyn Error>> {
+        let valid_key = !entry.key.is_empty()
+            && entry
+                .key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_key {
+            return Err(format!(
+                "Invalid sport key '{}': use lower case letters, digits and underscores",
+                entry.key
+            )
+            .into());
+        }
+        if entry.name.trim().is_empty() {
+            return Err(format!("Sport '{}' needs a name", entry.key).into());
+        }
+        for name in [&entry.key, &entry.name].into_iter().chain(&entry.aliases) {
+            if name.contains([',', ';']) {
+                return Err(format!("Invalid sport name '{}': commas and semicolons are not allowed", name).into());
+            }
+            if let Some(other) = existing.find(name).or_else(|| self.find(name)) {
+                return Err(format!("'{}' is already the sport {}", name, other.key).into());
+            }
+        }
+        self.sports.push(entry);
+        Ok(())
+    }
+}
+
+// Lower case without accents, spaces, hyphens or underscores, so `Water polo` matches
+// `water_polo` and `futbol` matches `Fútbol`
+fn normalize(s: &str) -> String {
+    fold_diacritics(s)
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Language of the messages locale, e.g. `es` or `en_GB`, following the gettext lookup order.
+pub fn current_language() -> Option<String> {
+    let value = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    // `LANGUAGE` may list several; `es_ES.UTF-8@euro` is just `es_ES`
+    let first = value.split(':').next().unwrap_or_default();
+    let language = first.split(['.', '@']).next().unwrap_or_default();
+    match language {
+        "" | "C" | "POSIX" => None,
+        _ => Some(language.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, name: &str, aliases: &[&str], translations: &[(&str, &str)]) -> SportEntry {
+        SportEntry {
+            key: key.to_string(),
+            name: name.to_string(),
+            emoji: String::new(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            category: String::new(),
+            translations: translations.iter().map(|(l, t)| (l.to_string(), t.to_string())).collect(),
+        }
+    }
+
+    fn key(found: Option<&SportEntry>) -> Option<&str> {
+        found.map(|e| e.key.as_str())
+    }
+
+    #[test]
+    fn built_in_catalog_has_unique_keys() {
+        let catalog = SportCatalog::built_in();
+        assert_eq!(catalog.sports.len(), 23);
+        for (i, sport) in catalog.sports.iter().enumerate() {
+            assert!(!sport.name.is_empty() && !sport.emoji.is_empty(), "{}", sport.key);
+            assert!(catalog.sports[i + 1..].iter().all(|other| other.key != sport.key), "{}", sport.key);
+        }
+        assert_eq!(catalog.get("soccer").unwrap().translation("en_GB"), Some("Football"));
+        assert_eq!(catalog.get("soccer").unwrap().translation("es_MX"), Some("Fútbol"));
+        assert_eq!(catalog.get("soccer").unwrap().translation("fr"), None);
+    }
+
+    #[test]
+    fn user_file_is_applied_over_the_built_in_sports() {
+        let dir = std::env::temp_dir().join(format!("sport-catalog-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sports.json");
+        fs::write(
+            &path,
+            r#"{"sports": [
+                {"key": "golf", "name": "Disc golf", "emoji": "🥏"},
+                {"key": "pickleball", "name": "Pickleball", "aliases": ["pickle"]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut catalog = SportCatalog::built_in();
+        catalog.apply(SportCatalog::read(&path).unwrap());
+        assert_eq!(catalog.sports.len(), 24);
+        assert_eq!(catalog.get("golf").unwrap().name, "Disc golf");
+        assert_eq!(catalog.sports.last().unwrap().key, "pickleball");
+        assert_eq!(key(catalog.find("Pickle")), Some("pickleball"));
+
+        fs::write(&path, r#"{"sports": [{"key": "golf"}]}"#).unwrap();
+        let error = SportCatalog::read(&path).unwrap_err().to_string();
+        assert!(error.starts_with(&path.display().to_string()), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(SportCatalog::read(&path).unwrap(), SportCatalog::default());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn added_sports_may_not_clash_with_known_names() {
+        let built_in = SportCatalog::built_in();
+        let mut user = SportCatalog::default();
+        user.add(entry("pickleball", "Pickleball", &["pickle"], &[]), &built_in).unwrap();
+
+        let error = |entry: SportEntry, user: &mut SportCatalog| user.add(entry, &built_in).unwrap_err().to_string();
+        assert_eq!(error(entry("Pickle Ball", "Pickleball", &[], &[]), &mut user), "Invalid sport key 'Pickle Ball': use lower case letters, digits and underscores");
+        assert_eq!(error(entry("padel", " ", &[], &[]), &mut user), "Sport 'padel' needs a name");
+        assert_eq!(error(entry("padel", "Padel", &["paddle; tennis"], &[]), &mut user), "Invalid sport name 'paddle; tennis': commas and semicolons are not allowed");
+        assert_eq!(error(entry("futbol", "Futbol", &[], &[]), &mut user), "'futbol' is already the sport soccer");
+        assert_eq!(error(entry("padel", "Padel", &["Jogging"], &[]), &mut user), "'Jogging' is already the sport running");
+        assert_eq!(error(entry("pickle", "Pickle", &[], &[]), &mut user), "'pickle' is already the sport pickleball");
+        assert_eq!(user.sports.len(), 1);
+    }
+
+    #[test]
+    fn find_reads_keys_then_preferred_translations_then_names_and_aliases_then_other_translations() {
+        let catalog = SportCatalog {
+            sports: vec![
+                entry("ball", "Ball", &["stick"], &[("es", "Pelota"), ("en_GB", "Racket")]),
+                entry("racket", "Racket", &["pelota"], &[("es", "Raqueta"), ("en_GB", "Stick")]),
+            ],
+        };
+        // A key wins over everything
+        assert_eq!(key(catalog.find_preferring("racket", Some("en_GB"))), Some("racket"));
+        // Then the preferred language
+        assert_eq!(key(catalog.find_preferring("Racket", Some("en_GB"))), Some("ball"));
+        assert_eq!(key(catalog.find_preferring("Stick", Some("en_GB"))), Some("racket"));
+        // Then names and aliases
+        assert_eq!(key(catalog.find_preferring("Racket", None)), Some("racket"));
+        assert_eq!(key(catalog.find_preferring("Stick", None)), Some("ball"));
+        assert_eq!(key(catalog.find_preferring("pelota", Some("es"))), Some("ball"));
+        assert_eq!(key(catalog.find_preferring("pelota", None)), Some("racket"));
+        // And other translations last, ignoring case and accents
+        assert_eq!(key(catalog.find_preferring("RAQUÉTA", None)), Some("racket"));
+        assert_eq!(catalog.find_preferring("bat", Some("en_GB")), None);
+    }
+
+    #[test]
+    fn suggestions_are_a_typo_away() {
+        let catalog = SportCatalog::built_in();
+        assert_eq!(key(catalog.suggest("socer")), Some("soccer"));
+        assert_eq!(key(catalog.suggest("basketbal")), Some("basketball"));
+        assert_eq!(key(catalog.suggest("snowbaording")), Some("snowboarding"));
+        assert_eq!(catalog.suggest("kabaddi"), None);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::birth_date::BirthDate;
+use crate::person::Person;
+use crate::relations::{Relation, RelationKind};
+use crate::store::PeopleStore;
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// Rows are read back in insertion order (rowid), which keeps the order people were saved in.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS people (
+    id             TEXT PRIMARY KEY NOT NULL,
+    first_name     TEXT NOT NULL,
+    last_name      TEXT NOT NULL,
+    date_of_birth  TEXT NOT NULL,
+    favorite_sport TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS relations (
+    from_id TEXT NOT NULL,
+    kind    TEXT NOT NULL,
+    to_id   TEXT NOT NULL
+);
+";
+
+// The changes from each version of the schema to the next, the version being stored in
+// `PRAGMA user_version`. Each runs in a transaction together with the version update, so an
+// interrupted upgrade is redone from where it stopped.
+const MIGRATIONS: &[&str] = &[
+    // 1: unknown dates of birth are stored as '' instead of 1900-01-01
+    "UPDATE people SET date_of_birth = '' WHERE date_of_birth = '1900-01-01';",
+    // 2: `custom`, a JSON object of custom fields
+    "ALTER TABLE people ADD COLUMN custom TEXT NOT NULL DEFAULT '{}';",
+    // 3: contact details, with `emails` and `phones` as JSON arrays
+    "ALTER TABLE people ADD COLUMN emails TEXT NOT NULL DEFAULT '[]';
+     ALTER TABLE people ADD COLUMN phones TEXT NOT NULL DEFAULT '[]';
+     ALTER TABLE people ADD COLUMN address TEXT NOT NULL DEFAULT '';
+     ALTER TABLE people ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+    // 4: `tags`, a JSON array
+    "ALTER TABLE people ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';",
+    // 5: `favorite_sports`, a JSON array with the primary sport first, replaces `favorite_sport`
+    "ALTER TABLE people ADD COLUMN favorite_sports TEXT NOT NULL DEFAULT '[]';
+     UPDATE people SET favorite_sports = json_array(favorite_sport);
+     ALTER TABLE people DROP COLUMN favorite_sport;",
+];
+
+/// The people database as a SQLite file. Every change runs in its own transaction.
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        SqliteStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn connect(&self) -> Result<Connection, Box<dyn Error>> {
+        let mut conn = Connection::open(&self.path)?;
+        conn.execute_batch(SCHEMA)?;
+        loop {
+            // The version is read within the transaction, so two programs opening the file
+            // at once do not both run a step
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let version: usize = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            let Some(migration) = MIGRATIONS.get(version) else { break };
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", version + 1)?;
+            tx.commit()?;
+        }
+        Ok(conn)
+    }
+
+    fn insert_row(conn: &Connection, person: &Person) -> Result<usize, Box<dyn Error>> {
+        Ok(conn.execute(
+            "INSERT INTO people (id, first_name, last_name, date_of_birth, favorite_sports, custom,
+                                 emails, phones, address, notes, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                person.id,
+                person.first_name,
+                person.last_name,
+                person.date_of_birth.to_string(),
+                serde_json::to_string(&person.favorite_sports)?,
+                serde_json::to_string(&person.custom)?,
+                serde_json::to_string(&person.emails)?,
+                serde_json::to_string(&person.phones)?,
+                person.address,
+                person.notes,
+                serde_json::to_string(&person.tags)?,
+            ],
+        )?)
+    }
+}
+
+impl PeopleStore for SqliteStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn create(&self) -> Result<(), Box<dyn Error>> {
+        self.connect()?;
+        log::info!("Created new SQLite database: {}", self.path.display());
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<Person>, Box<dyn Error>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, first_name, last_name, date_of_birth, favorite_sports, custom,
+                    emails, phones, address, notes, tags
+             FROM people ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, String>(8)?,
+                row.get::<_, String>(9)?,
+                row.get::<_, String>(10)?,
+            ))
+        })?;
+
+        let mut people = Vec::new();
+        for row in rows {
+            let (id, first_name, last_name, date_of_birth, favorite_sports, custom, emails, phones, address, notes, tags) = row?;
+            let date_of_birth = BirthDate::parse(&date_of_birth)
+                .ok_or_else(|| format!("Invalid date of birth for ID {}: {}", id, date_of_birth))?;
+            let favorite_sports = serde_json::from_str(&favorite_sports)
+                .map_err(|e| format!("Invalid favorite sports for ID {}: {}", id, e))?;
+            let custom = serde_json::from_str(&custom)
+                .map_err(|e| format!("Invalid custom fields for ID {}: {}", id, e))?;
+            let emails = serde_json::from_str(&emails)
+                .map_err(|e| format!("Invalid emails for ID {}: {}", id, e))?;
+            let phones = serde_json::from_str(&phones)
+                .map_err(|e| format!("Invalid phones for ID {}: {}", id, e))?;
+            let tags = serde_json::from_str(&tags)
+                .map_err(|e| format!("Invalid tags for ID {}: {}", id, e))?;
+            let mut person = Person::with_id(
+                id,
+                first_name,
+                last_name,
+                date_of_birth,
+                favorite_sports,
+            );
+            person.emails = emails;
+            person.phones = phones;
+            person.address = address;
+            person.notes = notes;
+            person.tags = tags;
+            person.custom = custom;
+            people.push(person);
+        }
+
+        log::info!("Read {} {} from SQLite database: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok(people)
+    }
+
+    fn save(&self, people: &[Person]) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM people", [])?;
+        for person in people {
+            Self::insert_row(&tx, person)?;
+        }
+        tx.commit()?;
+
+        log::info!("Wrote {} {} to SQLite database: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    fn insert(&self, person: &Person) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM people WHERE id = ?1)",
+            [&person.id],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(format!("Duplicate ID: {}", person.id).into());
+        }
+        Self::insert_row(&tx, person)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update(&self, person: &Person) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        let changed = tx.execute(
+            "UPDATE people
+             SET first_name = ?2, last_name = ?3, date_of_birth = ?4, favorite_sports = ?5, custom = ?6,
+                 emails = ?7, phones = ?8, address = ?9, notes = ?10, tags = ?11
+             WHERE id = ?1",
+            params![
+                person.id,
+                person.first_name,
+                person.last_name,
+                person.date_of_birth.to_string(),
+                serde_json::to_string(&person.favorite_sports)?,
+                serde_json::to_string(&person.custom)?,
+                serde_json::to_string(&person.emails)?,
+                serde_json::to_string(&person.phones)?,
+                person.address,
+                person.notes,
+                serde_json::to_string(&person.tags)?,
+            ],
+        )?;
+        if changed == 0 {
+            return Err(format!("No person with ID {}", person.id).into());
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        let changed = tx.execute("DELETE FROM people WHERE id = ?1", [id])?;
+        if changed == 0 {
+            return Err(format!("No person with ID {}", id).into());
+        }
+        tx.execute("DELETE FROM relations WHERE from_id = ?1 OR to_id = ?1", [id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Relationships are kept in the `relations` table of the same database.
+    fn load_relations(&self) -> Result<Vec<Relation>, Box<dyn Error>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare("SELECT from_id, kind, to_id FROM relations ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        let mut relations = Vec::new();
+        for row in rows {
+            let (from_id, kind, to_id) = row?;
+            let kind = RelationKind::parse(&kind)?;
+            relations.push(Relation { from_id, kind, to_id });
+        }
+        Ok(relations)
+    }
+
+    fn save_relations(&self, relations: &[Relation]) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM relations", [])?;
+        for relation in relations {
+            tx.execute(
+                "INSERT INTO relations (from_id, kind, to_id) VALUES (?1, ?2, ?3)",
+                params![relation.from_id, relation.kind.to_string(), relation.to_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::Sport;
+    use chrono::NaiveDate;
+    use std::fs;
+
+    // A path for a database of its own, with no file yet
+    fn database(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sqlite-store-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.sqlite", test));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn person(id: &str, first_name: &str, date_of_birth: BirthDate) -> Person {
+        Person::with_id(id.to_string(), first_name.to_string(), "Miller".to_string(), date_of_birth, vec![Sport::Tennis])
+    }
+
+    fn first_names(people: &[Person]) -> Vec<&str> {
+        people.iter().map(|p| p.first_name.as_str()).collect()
+    }
+
+    #[test]
+    fn migrates_a_version_0_database() {
+        let path = database("version-0");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE people (
+                 id TEXT PRIMARY KEY NOT NULL, first_name TEXT NOT NULL, last_name TEXT NOT NULL,
+                 date_of_birth TEXT NOT NULL, favorite_sport TEXT NOT NULL
+             );
+             INSERT INTO people VALUES ('0', 'Ann', 'Miller', '1900-01-01', 'Soccer');
+             INSERT INTO people VALUES ('1', 'Bob', 'Smith', '1990-05-06', 'tennis');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SqliteStore::new(&path);
+        let people = store.load().unwrap();
+        assert_eq!(people[0].date_of_birth, BirthDate::Unknown);
+        assert_eq!(people[0].favorite_sports, [Sport::Soccer]);
+        assert_eq!(people[1].date_of_birth, BirthDate::Full(NaiveDate::from_ymd_opt(1990, 5, 6).unwrap()));
+        assert_eq!(people[1].favorite_sports, [Sport::Tennis]);
+        assert!(people[0].emails.is_empty() && people[0].tags.is_empty() && people[0].custom.is_empty());
+
+        // Opening it again finds nothing left to do
+        assert_eq!(store.load().unwrap(), people);
+        let version: usize = Connection::open(&path)
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn people_and_relations_load_as_saved() {
+        let path = database("round-trip");
+        let store = SqliteStore::new(&path);
+        store.create().unwrap();
+
+        let mut ann = person("0", "Ann", BirthDate::Year(1990));
+        ann.favorite_sports = vec![Sport::Soccer, Sport::Other("Kabaddi".to_string())];
+        ann.emails = vec!["ann@example.com".to_string()];
+        ann.phones = vec!["+15551234567".to_string()];
+        ann.address = "1 Main St\nSpringfield".to_string();
+        ann.notes = "Allergic to nuts".to_string();
+        ann.tags = vec!["coach".to_string()];
+        ann.custom.insert("shirt_size".to_string(), "M".to_string());
+        let people = vec![
+            ann,
+            person("1", "Bob", BirthDate::MonthDay { month: 2, day: 29 }),
+            person("2", "Cid", BirthDate::Unknown),
+        ];
+        store.save(&people).unwrap();
+        assert_eq!(store.load().unwrap(), people);
+
+        let relations = vec![
+            Relation { from_id: "0".to_string(), kind: RelationKind::Parent, to_id: "1".to_string() },
+            Relation { from_id: "2".to_string(), kind: RelationKind::Custom("coach".to_string()), to_id: "1".to_string() },
+        ];
+        store.save_relations(&relations).unwrap();
+        assert_eq!(store.load_relations().unwrap(), relations);
+
+        // Deleting someone deletes their relationships
+        store.delete("0").unwrap();
+        assert_eq!(first_names(&store.load().unwrap()), ["Bob", "Cid"]);
+        assert_eq!(store.load_relations().unwrap(), relations[1..]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_changes_are_rolled_back() {
+        let path = database("rollback");
+        let store = SqliteStore::new(&path);
+        let people = vec![person("0", "Ann", BirthDate::Unknown), person("1", "Bob", BirthDate::Unknown)];
+        store.save(&people).unwrap();
+
+        // The second person breaks the primary key after everyone was deleted
+        let clashing = vec![person("5", "Cid", BirthDate::Unknown), person("5", "Dee", BirthDate::Unknown)];
+        assert!(store.save(&clashing).is_err());
+        assert_eq!(store.load().unwrap(), people);
+
+        assert!(store.insert(&person("1", "Eve", BirthDate::Unknown)).is_err());
+        assert!(store.update(&person("9", "Fay", BirthDate::Unknown)).is_err());
+        assert!(store.delete("9").is_err());
+        assert_eq!(store.load().unwrap(), people);
+
+        store.insert(&person("2", "Gus", BirthDate::Unknown)).unwrap();
+        store.update(&person("0", "Anne", BirthDate::Unknown)).unwrap();
+        assert_eq!(first_names(&store.load().unwrap()), ["Anne", "Bob", "Gus"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use crate::custom_fields::FieldSchema;
+use crate::json_store::JsonStore;
+use crate::person::{create_new_csv_file, fill_missing_ids, find_person_by_id, import_people, Person};
+use crate::relations::{self, add_relation, read_relations_csv, write_relations_csv, Relation};
+use crate::sqlite_store::SqliteStore;
+use crate::vcard_store::{VcardStore, VcardVersion};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// File formats the people database can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StoreFormat {
+    Csv,
+    Sqlite,
+    Json,
+    Ndjson,
+    /// vCard 4.0
+    Vcard,
+    /// vCard 3.0
+    Vcard3,
+}
+
+impl StoreFormat {
+    /// Guesses the format from a file extension. Unknown extensions are treated as CSV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> StoreFormat {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("csv") => StoreFormat::Csv,
+            Some("sqlite") | Some("sqlite3") | Some("db") => StoreFormat::Sqlite,
+            Some("json") => StoreFormat::Json,
+            Some("ndjson") | Some("jsonl") => StoreFormat::Ndjson,
+            Some("vcf") | Some("vcard") => StoreFormat::Vcard,
+            // Anything else is read as CSV, as before other formats existed
+            _ => StoreFormat::Csv,
+        }
+    }
+}
+
+/// A record that was skipped while loading.
+#[derive(Debug, Clone)]
+pub struct LoadProblem {
+    /// Line in the file where the record starts, if known
+    pub line: Option<u64>,
+    /// Column (field name) that could not be read, if the problem is limited to one
+    pub column: Option<String>,
+    pub reason: String,
+    /// The fields of the skipped record as they were in the file, for quarantining
+    pub record: Option<Vec<String>>,
+}
+
+impl std::fmt::Display for LoadProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}", line)?;
+        }
+        if let Some(column) = &self.column {
+            write!(f, "{}column {}", if self.line.is_some() { ", " } else { "" }, column)?;
+        }
+        if self.line.is_some() || self.column.is_some() {
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.reason)
+    }
+}
+
+/// Storage backend for the people database.
+///
+/// `load` and `save` work on the whole database. The incremental operations default to
+/// load-modify-save; backends that can do better override them.
+pub trait PeopleStore {
+    /// Location of the database, for messages and change detection.
+    fn path(&self) -> &Path;
+
+    /// Creates a new, empty database.
+    fn create(&self) -> Result<(), Box<dyn Error>>;
+
+    fn load(&self) -> Result<Vec<Person>, Box<dyn Error>>;
+
+    /// Loads the people that can be read and reports the records that had to be skipped.
+    /// By default nothing is skipped: any bad record makes `load` fail.
+    fn load_lenient(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        Ok((self.load()?, Vec::new()))
+    }
+
+    /// Loads the people like `load_lenient`, but leaves anyone the database has no ID for
+    /// without one, instead of the ID `load` would make up, so that importing them into
+    /// another database cannot replace someone there.
+    fn load_for_import(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        self.load_lenient()
+    }
+
+    /// Saves the records skipped by `load_lenient` to `destination`, so they are not lost
+    /// when the rest of the database is saved.
+    fn quarantine(&self, _problems: &[LoadProblem], destination: &Path) -> Result<(), Box<dyn Error>> {
+        Err(format!(
+            "Skipped records from {} cannot be saved to {}",
+            self.path().display(),
+            destination.display()
+        )
+        .into())
+    }
+
+    /// Describes the schema migrations that loading this database will apply.
+    fn pending_migrations(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    /// Replaces the whole database with `people`.
+    fn save(&self, people: &[Person]) -> Result<(), Box<dyn Error>>;
+
+    /// Adds one person, who must already have an ID that is not in use.
+    fn insert(&self, person: &Person) -> Result<(), Box<dyn Error>> {
+        let mut people = self.load()?;
+        if find_person_by_id(&people, &person.id).is_some() {
+            return Err(format!("Duplicate ID: {}", person.id).into());
+        }
+        people.push(person.clone());
+        self.save(&people)
+    }
+
+    /// Replaces the person with the same ID.
+    fn update(&self, person: &Person) -> Result<(), Box<dyn Error>> {
+        let mut people = self.load()?;
+        let index = find_person_by_id(&people, &person.id)
+            .ok_or_else(|| format!("No person with ID {}", person.id))?;
+        people[index] = person.clone();
+        self.save(&people)
+    }
+
+    /// Removes the person with the given ID, along with their relationships.
+    fn delete(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let mut people = self.load()?;
+        let index = find_person_by_id(&people, id).ok_or_else(|| format!("No person with ID {}", id))?;
+        people.remove(index);
+        self.save(&people)?;
+        let mut relations = self.load_relations()?;
+        if relations::retain_valid(&mut relations, &people) > 0 {
+            self.save_relations(&relations)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the relationships between people. By default they are kept in a
+    /// `<database>.relations.csv` file next to the database.
+    fn load_relations(&self) -> Result<Vec<Relation>, Box<dyn Error>> {
+        read_relations_csv(relations::path_for(self.path()))
+    }
+
+    /// Replaces all relationships between people.
+    fn save_relations(&self, relations: &[Relation]) -> Result<(), Box<dyn Error>> {
+        write_relations_csv(relations::path_for(self.path()), relations)
+    }
+}
+
+/// Path of a file kept next to the database at `database`, named after it with `suffix`
+/// appended, e.g. `people.csv.fields.json`.
+pub fn companion_path<P: AsRef<Path>>(database: P, suffix: &str) -> PathBuf {
+    let mut name = database
+        .as_ref()
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(suffix);
+    database.as_ref().with_file_name(name)
+}
+
+/// The people database as a CSV file.
+pub struct CsvStore {
+    path: PathBuf,
+}
+
+impl CsvStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        CsvStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl PeopleStore for CsvStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn create(&self) -> Result<(), Box<dyn Error>> {
+        create_new_csv_file(&self.path)
+    }
+
+    fn load(&self) -> Result<Vec<Person>, Box<dyn Error>> {
+        Person::read_from_csv(&self.path)
+    }
+
+    fn load_lenient(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        Person::read_from_csv_lenient(&self.path)
+    }
+
+    fn load_for_import(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        Person::read_from_csv_keeping_ids(&self.path)
+    }
+
+    fn quarantine(&self, problems: &[LoadProblem], destination: &Path) -> Result<(), Box<dyn Error>> {
+        Person::write_quarantine_csv(&self.path, problems, destination)
+    }
+
+    fn pending_migrations(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Person::pending_csv_migrations(&self.path)
+    }
+
+    fn save(&self, people: &[Person]) -> Result<(), Box<dyn Error>> {
+        Person::write_to_csv(&self.path, people)
+    }
+}
+
+/// Opens the database at `path` in the given format, or the one its extension suggests.
+pub fn open_store<P: AsRef<Path>>(path: P, format: Option<StoreFormat>) -> Box<dyn PeopleStore> {
+    match format.unwrap_or_else(|| StoreFormat::from_path(&path)) {
+        StoreFormat::Csv => Box::new(CsvStore::new(path)),
+        StoreFormat::Sqlite => Box::new(SqliteStore::new(path)),
+        StoreFormat::Json => Box::new(JsonStore::new(path)),
+        StoreFormat::Ndjson => Box::new(JsonStore::new_lines(path)),
+        StoreFormat::Vcard => Box::new(VcardStore::new(path, VcardVersion::V4)),
+        StoreFormat::Vcard3 => Box::new(VcardStore::new(path, VcardVersion::V3)),
+    }
+}
+
+/// What `import_from` brought into a database.
+pub struct ImportOutcome {
+    pub added: usize,
+    pub updated: usize,
+    /// Records of the source that could not be read
+    pub problems: Vec<LoadProblem>,
+    /// Whether definitions of custom fields were added to the schema
+    pub new_fields: bool,
+    /// Whether relationships were added
+    pub new_relations: bool,
+}
+
+/// Reads the people of `source` into `people`, skipping records that cannot be read. People
+/// with an ID replace whoever has it in `people`; the others are added with a new ID. Their
+/// relationships come along when both people are in `people`, and so do the definitions of
+/// custom fields that `schema` does not have yet.
+pub fn import_from<P: AsRef<Path>>(
+    source: P,
+    format: Option<StoreFormat>,
+    people: &mut Vec<Person>,
+    relations: &mut Vec<Relation>,
+    schema: &mut FieldSchema,
+) -> Result<ImportOutcome, Box<dyn Error>> {
+    let source_store = open_store(&source, format);
+    let (mut imported, problems) = source_store.load_for_import()?;
+    // The IDs the source gives people who have none, which its relationships refer to
+    let mut source_ids = imported.clone();
+    fill_missing_ids(&mut source_ids);
+    let source_schema = FieldSchema::load_for(&source)?;
+    let new_fields = source_schema.fields.iter().any(|f| schema.get(&f.name).is_none());
+    schema.merge(&source_schema);
+    let (added, updated) = import_people(people, &mut imported);
+    let target_id = |id: &str| {
+        source_ids.iter().position(|p| p.id == id).map(|i| imported[i].id.clone())
+    };
+    let before = relations.len();
+    for relation in source_store.load_relations()? {
+        if let (Some(from_id), Some(to_id)) = (target_id(&relation.from_id), target_id(&relation.to_id)) {
+            let _ = add_relation(relations, people, Relation { from_id, to_id, ..relation });
+        }
+    }
+    Ok(ImportOutcome { added, updated, problems, new_fields, new_relations: relations.len() > before })
+}
+
+/// Writes `people` to `destination` together with their relationships and the definitions of
+/// the custom fields, so that converting to another format loses nothing.
+pub fn export_to<P: AsRef<Path>>(
+    destination: P,
+    format: Option<StoreFormat>,
+    people: &[Person],
+    relations: &[Relation],
+    schema: &FieldSchema,
+) -> Result<(), Box<dyn Error>> {
+    if !schema.fields.is_empty() {
+        schema.save_for(&destination)?;
+    }
+    let store = open_store(&destination, format);
+    store.save(people)?;
+    if !relations.is_empty() {
+        store.save_relations(relations)?;
+    }
+    Ok(())
+}
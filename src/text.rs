@@ -0,0 +1,113 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Removes accents from letters, e.g. `Fútbol` becomes `Futbol` and `Nguyễn` becomes `Nguyen`.
+/// Letters are decomposed (Unicode NFD) and their combining marks dropped; the few letters that
+/// do not decompose, such as `ø` or `đ`, are mapped to the plain letter. Other characters are
+/// kept as they are.
+pub fn fold_diacritics(s: &str) -> String {
+    s.nfd().filter(|&c| !is_combining_mark(c)).map(fold_char).collect()
+}
+
+/// The words of `s` in lower case without accents or punctuation, for comparing names:
+/// `José-Luis O'Brien` gives `jose`, `luis` and `obrien`.
+pub fn name_words(s: &str) -> Vec<String> {
+    let mut folded = String::new();
+    for c in fold_diacritics(s).chars().flat_map(char::to_lowercase) {
+        match c {
+            '\'' | '’' => {}
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            _ if c.is_alphanumeric() => folded.push(c),
+            _ => folded.push(' '),
+        }
+    }
+    folded.split_whitespace().map(str::to_string).collect()
+}
+
+// Letters with a stroke or without a dot, which have no decomposition
+fn fold_char(c: char) -> char {
+    match c {
+        'ı' => 'i',
+        'đ' => 'd',
+        'Đ' => 'D',
+        'ħ' => 'h',
+        'Ħ' => 'H',
+        'ł' => 'l',
+        'Ł' => 'L',
+        'ø' => 'o',
+        'Ø' => 'O',
+        'ŧ' => 't',
+        'Ŧ' => 'T',
+        _ => c,
+    }
+}
+
+/// Number of single-character insertions, deletions, substitutions and swaps of neighbouring
+/// characters needed to turn `a` into `b`, so `socer` is 1 away from `soccer`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows for the previous two prefixes of `a` and the current one
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_diacritics_removes_accents() {
+        assert_eq!(fold_diacritics("Fútbol"), "Futbol");
+        assert_eq!(fold_diacritics("Nguyễn Thị Hương"), "Nguyen Thi Huong");
+        assert_eq!(fold_diacritics("Ștefan Țurcanu"), "Stefan Turcanu");
+        assert_eq!(fold_diacritics("Erdoğan Őry Műller Ŕ"), "Erdogan Ory Muller R");
+        assert_eq!(fold_diacritics("Søren Łukasz Đorđe Ħal Iı"), "Soren Lukasz Dorde Hal Ii");
+    }
+
+    #[test]
+    fn fold_diacritics_handles_decomposed_text() {
+        assert_eq!(fold_diacritics("Jose\u{301}"), "Jose");
+        assert_eq!(fold_diacritics("José"), fold_diacritics("Jose\u{301}"));
+    }
+
+    #[test]
+    fn fold_diacritics_keeps_other_characters() {
+        assert_eq!(fold_diacritics("O'Brien-Smith 3"), "O'Brien-Smith 3");
+        assert_eq!(fold_diacritics("李小龍"), "李小龍");
+        assert_eq!(fold_diacritics(""), "");
+    }
+
+    #[test]
+    fn name_words_splits_and_normalizes() {
+        assert_eq!(name_words("José-Luis O'Brien"), ["jose", "luis", "obrien"]);
+        assert_eq!(name_words("  Nguyễn   VĂN  "), ["nguyen", "van"]);
+        assert_eq!(name_words("Straße Æsop"), ["strasse", "aesop"]);
+        assert_eq!(name_words("D’Angelo"), ["dangelo"]);
+        assert!(name_words(" - , ").is_empty());
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one_edit() {
+        assert_eq!(edit_distance("socer", "soccer"), 1);
+        assert_eq!(edit_distance("tennsi", "tennis"), 1);
+        assert_eq!(edit_distance("", "golf"), 4);
+        assert_eq!(edit_distance("golf", "golf"), 0);
+    }
+}
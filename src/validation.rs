@@ -0,0 +1,430 @@
+use crate::birth_date::BirthDate;
+use crate::constants::Sport;
+use crate::person::Person;
+use crate::sport_catalog::{catalog, SportCatalog};
+use chrono::{Datelike, Local, NaiveDate};
+use gettextrs::gettext;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt;
+
+/// Longest first or last name accepted, in characters.
+pub const MAX_NAME_LENGTH: usize = 100;
+/// Longest custom sport name accepted, in characters.
+pub const MAX_SPORT_LENGTH: usize = 50;
+/// Oldest age a date of birth may imply, in years.
+pub const MAX_AGE_YEARS: i32 = 130;
+/// Longest tag accepted, in characters.
+pub const MAX_TAG_LENGTH: usize = 30;
+
+// Something@domain.tld, without spaces or characters that separate addresses in lists
+static EMAIL_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[^@\s,;<>]+@[^@\s,;<>.]+(\.[^@\s,;<>.]+)+$").unwrap());
+
+/// A field of `Person` that can fail validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    FirstName,
+    LastName,
+    DateOfBirth,
+    FavoriteSport,
+    Email,
+    Phone,
+    Tags,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Field::FirstName => gettext("First Name"),
+            Field::LastName => gettext("Last Name"),
+            Field::DateOfBirth => gettext("Date of Birth"),
+            Field::FavoriteSport => gettext("Favorite Sport"),
+            Field::Email => gettext("Email"),
+            Field::Phone => gettext("Phone"),
+            Field::Tags => gettext("Tags"),
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Why a field was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// The field is missing or only whitespace
+    Empty(Field),
+    TooLong { field: Field, max: usize },
+    /// The text given for the date of birth is not in any of the forms `BirthDate` accepts
+    InvalidDate(String),
+    FutureDate(BirthDate),
+    /// The date of birth is more than `MAX_AGE_YEARS` ago
+    TooOld(BirthDate),
+    InvalidEmail(String),
+    /// The text given is not a phone number with a country code
+    InvalidPhone(String),
+    /// The tag contains a character used to separate tags in lists
+    InvalidTag(String),
+    /// The custom sport contains a character used to separate sports in lists
+    InvalidSport(String),
+    /// The sport is not in the catalog but is a typo away from one that is
+    UnknownSport { input: String, suggestion: Sport },
+}
+
+impl ValidationError {
+    /// The field the error is about.
+    pub fn field(&self) -> Field {
+        match self {
+            ValidationError::Empty(field) | ValidationError::TooLong { field, .. } => *field,
+            ValidationError::InvalidDate(_)
+            | ValidationError::FutureDate(_)
+            | ValidationError::TooOld(_) => Field::DateOfBirth,
+            ValidationError::InvalidEmail(_) => Field::Email,
+            ValidationError::InvalidPhone(_) => Field::Phone,
+            ValidationError::InvalidTag(_) => Field::Tags,
+            ValidationError::InvalidSport(_) | ValidationError::UnknownSport { .. } => Field::FavoriteSport,
+        }
+    }
+
+    /// The error without the field name, for showing next to the field.
+    pub fn message(&self) -> String {
+        match self {
+            ValidationError::Empty(_) => gettext("is required"),
+            ValidationError::TooLong { max, .. } => {
+                gettext("must be at most {max} characters").replace("{max}", &max.to_string())
+            }
+            ValidationError::InvalidDate(input) => {
+                format!("'{}' {}", input, gettext("is not a valid date, expected YYYY-MM-DD, YYYY or --MM-DD"))
+            }
+            ValidationError::FutureDate(_) => gettext("is in the future"),
+            ValidationError::TooOld(_) => gettext("is more than {years} years ago")
+                .replace("{years}", &MAX_AGE_YEARS.to_string()),
+            ValidationError::InvalidEmail(input) => {
+                format!("'{}' {}", input, gettext("is not a valid email address"))
+            }
+            ValidationError::InvalidPhone(input) => format!(
+                "'{}' {}",
+                input,
+                gettext("is not a valid phone number, include the country code, e.g. +1 555 123 4567")
+            ),
+            ValidationError::InvalidTag(input) => {
+                format!("'{}' {}", input, gettext("is not a valid tag, tags cannot contain commas or semicolons"))
+            }
+            ValidationError::InvalidSport(input) => {
+                format!("'{}' {}", input, gettext("is not a valid sport, sports cannot contain commas or semicolons"))
+            }
+            ValidationError::UnknownSport { input, suggestion } => format!(
+                "'{}' {}",
+                input,
+                gettext("is not a known sport, did you mean {sport}? Use `sports add` for a new sport")
+                    .replace("{sport}", &suggestion.to_string())
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field(), self.message())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// All the problems found with a person, in field order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Checks a first or last name: not blank and not longer than `MAX_NAME_LENGTH`.
+pub fn validate_name(field: Field, name: &str) -> Result<(), ValidationError> {
+    let name = name.trim();
+    if name.is_empty() {
+        Err(ValidationError::Empty(field))
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        Err(ValidationError::TooLong { field, max: MAX_NAME_LENGTH })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that a date of birth is not in the future and not implausibly long ago. Only the
+/// known part is checked; a birthday without a year is always valid.
+pub fn validate_date_of_birth(date: BirthDate) -> Result<(), ValidationError> {
+    let today = Local::now().naive_local().date();
+    let oldest = today
+        .with_year(today.year() - MAX_AGE_YEARS)
+        .unwrap_or_else(|| NaiveDate::from_ymd_opt(today.year() - MAX_AGE_YEARS, 3, 1).unwrap());
+    let (too_new, too_old) = match date {
+        BirthDate::Full(dob) => (dob > today, dob < oldest),
+        BirthDate::Year(year) => (year > today.year(), year < oldest.year()),
+        BirthDate::MonthDay { .. } | BirthDate::Unknown => (false, false),
+    };
+    if too_new {
+        Err(ValidationError::FutureDate(date))
+    } else if too_old {
+        Err(ValidationError::TooOld(date))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parses a date of birth typed by the user and validates it. Blank input means unknown.
+pub fn parse_date_of_birth(input: &str) -> Result<BirthDate, ValidationError> {
+    let input = input.trim();
+    let date = BirthDate::parse(input).ok_or_else(|| ValidationError::InvalidDate(input.to_string()))?;
+    validate_date_of_birth(date)?;
+    Ok(date)
+}
+
+/// Checks the syntax of an email address and returns it trimmed.
+pub fn parse_email(input: &str) -> Result<String, ValidationError> {
+    let input = input.trim();
+    if EMAIL_PATTERN.is_match(input) {
+        Ok(input.to_string())
+    } else {
+        Err(ValidationError::InvalidEmail(input.to_string()))
+    }
+}
+
+/// Normalizes a phone number to E.164, e.g. `+15551234567`. Spaces, dashes, dots, slashes
+/// and parentheses are dropped, and a leading `00` is read as `+`. The country code is
+/// required, since there is no way to tell which country a local number belongs to.
+pub fn parse_phone(input: &str) -> Result<String, ValidationError> {
+    let input = input.trim();
+    let compact: String = input
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '/' | '(' | ')'))
+        .collect();
+    let digits = compact
+        .strip_prefix('+')
+        .or_else(|| compact.strip_prefix("00"))
+        .unwrap_or("");
+    // E.164 numbers have at most 15 digits, and country codes never start with 0
+    let valid = (7..=15).contains(&digits.len())
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && !digits.starts_with('0');
+    if valid {
+        Ok(format!("+{}", digits))
+    } else {
+        Err(ValidationError::InvalidPhone(input.to_string()))
+    }
+}
+
+/// Checks a tag and returns it trimmed and in lower case, so `Coach` and `coach` are the same tag.
+pub fn parse_tag(input: &str) -> Result<String, ValidationError> {
+    let tag = input.trim().to_lowercase();
+    if tag.is_empty() {
+        Err(ValidationError::Empty(Field::Tags))
+    } else if tag.contains([',', ';']) {
+        Err(ValidationError::InvalidTag(input.trim().to_string()))
+    } else if tag.chars().count() > MAX_TAG_LENGTH {
+        Err(ValidationError::TooLong { field: Field::Tags, max: MAX_TAG_LENGTH })
+    } else {
+        Ok(tag)
+    }
+}
+
+/// Checks that a custom sport has a name of reasonable length. Known sports are always valid.
+pub fn validate_sport(sport: &Sport) -> Result<(), ValidationError> {
+    match sport {
+        Sport::Other(name) if name.trim().is_empty() => Err(ValidationError::Empty(Field::FavoriteSport)),
+        Sport::Other(name) if name.trim().chars().count() > MAX_SPORT_LENGTH => Err(ValidationError::TooLong {
+            field: Field::FavoriteSport,
+            max: MAX_SPORT_LENGTH,
+        }),
+        Sport::Other(name) if name.contains([',', ';']) => Err(ValidationError::InvalidSport(name.trim().to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// Reads a sport typed by the user. Names that are not in the catalog are custom sports,
+/// unless they look like a typo of a sport that is.
+pub fn parse_sport(input: &str) -> Result<Sport, ValidationError> {
+    parse_sport_in(input, catalog())
+}
+
+// Reads a sport like `parse_sport`, looking it up in `catalog`. Sports added to the catalog
+// by the user are `Sport::Other` too, but they are known
+fn parse_sport_in(input: &str, catalog: &SportCatalog) -> Result<Sport, ValidationError> {
+    let sport = match catalog.find(input) {
+        Some(entry) => Sport::from_entry(entry),
+        None => {
+            if let Some(entry) = catalog.suggest(input) {
+                return Err(ValidationError::UnknownSport {
+                    input: input.trim().to_string(),
+                    suggestion: Sport::from_entry(entry),
+                });
+            }
+            Sport::Other(input.trim().to_string())
+        }
+    };
+    validate_sport(&sport)?;
+    Ok(sport)
+}
+
+/// Checks every field of `person`, collecting all the errors.
+pub fn validate_person(person: &Person) -> Result<(), ValidationErrors> {
+    let mut errors: Vec<ValidationError> = [
+        validate_name(Field::FirstName, &person.first_name),
+        validate_name(Field::LastName, &person.last_name),
+        validate_date_of_birth(person.date_of_birth),
+    ]
+    .into_iter()
+    .filter_map(Result::err)
+    .collect();
+    if person.favorite_sports.is_empty() {
+        errors.push(ValidationError::Empty(Field::FavoriteSport));
+    }
+    errors.extend(person.favorite_sports.iter().filter_map(|s| validate_sport(s).err()));
+    errors.extend(person.emails.iter().filter_map(|e| parse_email(e).err()));
+    errors.extend(person.phones.iter().filter_map(|p| parse_phone(p).err()));
+    errors.extend(person.tags.iter().filter_map(|t| parse_tag(t).err()));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors(errors))
+    }
+}
+
+/// Checks `person` like `validate_person`, but accepts what the person already had before the
+/// change (`previous`, if any): no favorite sport, or phone numbers that cannot be normalized.
+/// Imports keep those as they are, and the people must stay editable.
+pub fn validate_change(person: &Person, previous: Option<&Person>) -> Result<(), ValidationErrors> {
+    let Err(ValidationErrors(errors)) = validate_person(person) else { return Ok(()) };
+    let Some(previous) = previous else { return Err(ValidationErrors(errors)) };
+    let errors: Vec<ValidationError> = errors
+        .into_iter()
+        .filter(|e| match e {
+            ValidationError::Empty(Field::FavoriteSport) => {
+                !(person.favorite_sports.is_empty() && previous.favorite_sports.is_empty())
+            }
+            ValidationError::InvalidPhone(phone) => !previous.phones.contains(phone),
+            _ => true,
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sport_catalog::SportEntry;
+
+    fn person(phones: &[&str], favorite_sports: Vec<Sport>) -> Person {
+        let mut person = Person::new("Ann".to_string(), "Miller".to_string(), BirthDate::Unknown, favorite_sports);
+        person.phones = phones.iter().map(|p| p.to_string()).collect();
+        person
+    }
+
+    fn invalid_phone(input: &str) -> Result<String, ValidationError> {
+        Err(ValidationError::InvalidPhone(input.to_string()))
+    }
+
+    #[test]
+    fn phone_numbers_are_normalized_to_e164() {
+        assert_eq!(parse_phone(" +1 (555) 123-4567 "), Ok("+15551234567".to_string()));
+        assert_eq!(parse_phone("0044 20.7946/0958"), Ok("+442079460958".to_string()));
+        assert_eq!(parse_phone("+1234567"), Ok("+1234567".to_string()));
+        assert_eq!(parse_phone("+123456789012345"), Ok("+123456789012345".to_string()));
+    }
+
+    #[test]
+    fn phone_numbers_need_a_country_code_and_7_to_15_digits() {
+        assert_eq!(parse_phone("555 123 4567"), invalid_phone("555 123 4567"));
+        assert_eq!(parse_phone("+123456"), invalid_phone("+123456"));
+        assert_eq!(parse_phone("+1234567890123456"), invalid_phone("+1234567890123456"));
+        assert_eq!(parse_phone("+0 555 123 4567"), invalid_phone("+0 555 123 4567"));
+        assert_eq!(parse_phone("000 555 123 4567"), invalid_phone("000 555 123 4567"));
+        assert_eq!(parse_phone("+1 555 CALL ANN"), invalid_phone("+1 555 CALL ANN"));
+    }
+
+    #[test]
+    fn email_addresses() {
+        assert_eq!(parse_email(" ann@example.co.uk "), Ok("ann@example.co.uk".to_string()));
+        assert_eq!(parse_email("ann.miller+club@example.com"), Ok("ann.miller+club@example.com".to_string()));
+        for invalid in ["ann", "ann@example", "ann@@example.com", "ann miller@example.com", "a@example.com, b@example.com"] {
+            assert_eq!(parse_email(invalid), Err(ValidationError::InvalidEmail(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn edits_keep_what_the_person_already_had() {
+        let imported = person(&["555 0100"], Vec::new());
+        // Changing something else keeps the number that cannot be normalized and the missing sport
+        let mut edited = imported.clone();
+        edited.first_name = "Anne".to_string();
+        assert_eq!(validate_change(&edited, Some(&imported)), Ok(()));
+
+        // New invalid numbers and removing the last sport are still rejected
+        let edited = person(&["555 0100", "555 0199"], Vec::new());
+        assert_eq!(
+            validate_change(&edited, Some(&imported)),
+            Err(ValidationErrors(vec![ValidationError::InvalidPhone("555 0199".to_string())]))
+        );
+        let edited = person(&[], Vec::new());
+        assert_eq!(
+            validate_change(&edited, Some(&person(&[], vec![Sport::Tennis]))),
+            Err(ValidationErrors(vec![ValidationError::Empty(Field::FavoriteSport)]))
+        );
+    }
+
+    #[test]
+    fn new_people_must_be_fully_valid() {
+        assert_eq!(
+            validate_change(&person(&["555 0100"], Vec::new()), None),
+            Err(ValidationErrors(vec![
+                ValidationError::Empty(Field::FavoriteSport),
+                ValidationError::InvalidPhone("555 0100".to_string()),
+            ]))
+        );
+        assert_eq!(validate_change(&person(&["+1 555 123 4567"], vec![Sport::Tennis]), None), Ok(()));
+    }
+
+    #[test]
+    fn sports_added_by_the_user_are_known() {
+        let mut catalog = SportCatalog::built_in();
+        let mut user_catalog = SportCatalog::default();
+        let pickleball = SportEntry {
+            key: "pickleball".to_string(),
+            name: "Pickleball".to_string(),
+            emoji: String::new(),
+            aliases: vec!["pickle".to_string()],
+            category: "racket".to_string(),
+            translations: Default::default(),
+        };
+        user_catalog.add(pickleball, &catalog).unwrap();
+        catalog.sports.extend(user_catalog.sports);
+
+        let sport = Sport::Other("Pickleball".to_string());
+        assert_eq!(parse_sport_in("pickleball", &catalog), Ok(sport.clone()));
+        assert_eq!(parse_sport_in(" Pickle ", &catalog), Ok(sport.clone()));
+        assert_eq!(
+            parse_sport_in("picklebal", &catalog),
+            Err(ValidationError::UnknownSport { input: "picklebal".to_string(), suggestion: sport })
+        );
+    }
+
+    #[test]
+    fn sports_a_typo_away_from_a_known_one_are_rejected() {
+        let catalog = SportCatalog::built_in();
+        assert_eq!(parse_sport_in("tennis", &catalog), Ok(Sport::Tennis));
+        assert_eq!(
+            parse_sport_in("socer", &catalog),
+            Err(ValidationError::UnknownSport { input: "socer".to_string(), suggestion: Sport::Soccer })
+        );
+        assert_eq!(parse_sport_in("Kabaddi", &catalog), Ok(Sport::Other("Kabaddi".to_string())));
+    }
+}
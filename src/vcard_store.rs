@@ -0,0 +1,520 @@
+use crate::atomic_file::write_atomically;
+use crate::birth_date::BirthDate;
+use crate::constants::Sport;
+use crate::person::Person;
+use crate::store::{LoadProblem, PeopleStore};
+use crate::validation::parse_phone;
+use chrono::{Datelike, NaiveDate};
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Custom property holding a favorite sport, repeated for each one with the primary sport first
+const SPORT_PROPERTY: &str = "X-FAVORITE-SPORT";
+// Prefix used when the sport is kept in a NOTE instead, as some address books drop X- properties
+const SPORT_NOTE_PREFIX: &str = "Favorite sport:";
+
+/// vCard version written on export. Both are accepted on import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcardVersion {
+    V3,
+    V4,
+}
+
+/// People as a vCard (.vcf) file with one contact per person.
+///
+/// `first_name`/`last_name` map to `N` and `FN`, `date_of_birth` to `BDAY`, the ID to `UID`,
+/// each favorite sport to an `X-FAVORITE-SPORT` line, the contact details to `EMAIL`, `TEL`, `ADR`
+/// and `NOTE`, and the tags to `CATEGORIES`.
+pub struct VcardStore {
+    path: PathBuf,
+    version: VcardVersion,
+}
+
+impl VcardStore {
+    pub fn new<P: AsRef<Path>>(path: P, version: VcardVersion) -> Self {
+        VcardStore {
+            path: path.as_ref().to_path_buf(),
+            version,
+        }
+    }
+}
+
+impl PeopleStore for VcardStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn create(&self) -> Result<(), Box<dyn Error>> {
+        self.save(&[])
+    }
+
+    /// Loads all contacts that can be mapped; the others are logged and skipped.
+    fn load(&self) -> Result<Vec<Person>, Box<dyn Error>> {
+        let (people, problems) = self.load_lenient()?;
+        for problem in problems {
+            log::warn!("Skipped contact in {}: {}", self.path.display(), problem);
+        }
+        Ok(people)
+    }
+
+    fn load_lenient(&self) -> Result<(Vec<Person>, Vec<LoadProblem>), Box<dyn Error>> {
+        let content = fs::read_to_string(&self.path)?;
+        let (people, problems) = parse_vcards(&content);
+        log::info!("Read {} {} from vCard file: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok((people, problems))
+    }
+
+    fn save(&self, people: &[Person]) -> Result<(), Box<dyn Error>> {
+        write_atomically(&self.path, |file| {
+            for person in people {
+                file.write_all(format_vcard(person, self.version).as_bytes())?;
+            }
+            Ok(())
+        })?;
+        log::info!("Wrote {} {} to vCard file: {}",
+            people.len(),
+            if people.len() == 1 {"person" } else { "people" },
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Formats one person as a vCard, with CRLF line endings and folded long lines.
+pub fn format_vcard(person: &Person, version: VcardVersion) -> String {
+    let version_str = match version {
+        VcardVersion::V3 => "3.0",
+        VcardVersion::V4 => "4.0",
+    };
+    // vCard 3.0 has no way to write a partial date, so those are left out
+    let bday = match (version, person.date_of_birth) {
+        (VcardVersion::V3, BirthDate::Full(date)) => Some(date.format("%Y-%m-%d").to_string()),
+        (VcardVersion::V4, BirthDate::Full(date)) => Some(date.format("%Y%m%d").to_string()),
+        (VcardVersion::V4, BirthDate::Year(year)) => Some(format!("{:04}", year)),
+        (VcardVersion::V4, BirthDate::MonthDay { month, day }) => Some(format!("--{:02}{:02}", month, day)),
+        _ => None,
+    };
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        format!("VERSION:{}", version_str),
+    ];
+    if !person.id.is_empty() {
+        lines.push(format!("UID:{}", escape(&person.id)));
+    }
+    lines.push(format!(
+        "N:{};{};;;",
+        escape(&person.last_name),
+        escape(&person.first_name)
+    ));
+    lines.push(format!(
+        "FN:{}",
+        escape(format!("{} {}", person.first_name, person.last_name).trim())
+    ));
+    if let Some(bday) = bday {
+        lines.push(format!("BDAY:{}", bday));
+    }
+    for sport in &person.favorite_sports {
+        lines.push(format!("{}:{}", SPORT_PROPERTY, escape(&sport.canonical())));
+    }
+    for email in &person.emails {
+        lines.push(format!("EMAIL:{}", escape(email)));
+    }
+    for phone in &person.phones {
+        lines.push(match version {
+            VcardVersion::V3 => format!("TEL:{}", escape(phone)),
+            VcardVersion::V4 => format!("TEL;VALUE=uri:tel:{}", phone),
+        });
+    }
+    // The address is free text, so all of it goes in the street component
+    if !person.address.is_empty() {
+        lines.push(format!("ADR:;;{};;;;", escape(&person.address)));
+    }
+    if !person.notes.is_empty() {
+        lines.push(format!("NOTE:{}", escape(&person.notes)));
+    }
+    if !person.tags.is_empty() {
+        let tags: Vec<String> = person.tags.iter().map(|t| escape(t)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    lines.push("END:VCARD".to_string());
+
+    lines.iter().map(|l| fold(l)).collect::<Vec<_>>().join("")
+}
+
+/// Parses every contact in a vCard file. Contacts that cannot be mapped to a person
+/// are reported with the line their `BEGIN:VCARD` is on.
+pub fn parse_vcards(content: &str) -> (Vec<Person>, Vec<LoadProblem>) {
+    let mut people = Vec::new();
+    let mut problems = Vec::new();
+    // Properties of the contact being read, and the line it started on
+    let mut current: Option<(u64, Vec<(String, String)>)> = None;
+
+    for (line_no, line) in unfold(content) {
+        let Some((name, value)) = split_property(&line) else {
+            continue;
+        };
+        match (name.as_str(), &mut current) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VCARD") => {
+                current = Some((line_no, Vec::new()));
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VCARD") => {
+                let (start, properties) = current.take().unwrap();
+                match contact_to_person(&properties) {
+                    Ok(person) => people.push(person),
+                    Err(reason) => problems.push(LoadProblem {
+                        line: Some(start),
+                        column: None,
+                        reason,
+                        record: None,
+                    }),
+                }
+            }
+            (_, Some((_, properties))) => properties.push((name, value)),
+            _ => {}
+        }
+    }
+
+    if let Some((start, _)) = current {
+        problems.push(LoadProblem {
+            line: Some(start),
+            column: None,
+            reason: "contact is missing END:VCARD".to_string(),
+            record: None,
+        });
+    }
+
+    (people, problems)
+}
+
+fn contact_to_person(properties: &[(String, String)]) -> Result<Person, String> {
+    let get = |name: &str| {
+        properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    if let Some(version) = get("VERSION") {
+        if !matches!(version.trim(), "3.0" | "4.0") {
+            return Err(format!("unsupported vCard version {}", version.trim()));
+        }
+    }
+
+    let (mut first_name, mut last_name) = match get("N") {
+        Some(n) => {
+            let parts = split_unescaped(n, ';');
+            (
+                parts.get(1).map(|s| unescape(s)).unwrap_or_default(),
+                parts.first().map(|s| unescape(s)).unwrap_or_default(),
+            )
+        }
+        None => (String::new(), String::new()),
+    };
+    if first_name.trim().is_empty() && last_name.trim().is_empty() {
+        let full_name = get("FN").map(unescape).unwrap_or_default();
+        match full_name.trim().rsplit_once(' ') {
+            Some((first, last)) => {
+                first_name = first.trim().to_string();
+                last_name = last.trim().to_string();
+            }
+            None => first_name = full_name.trim().to_string(),
+        }
+    }
+    if first_name.trim().is_empty() && last_name.trim().is_empty() {
+        return Err("contact has no name (N or FN)".to_string());
+    }
+
+    let date_of_birth = match get("BDAY") {
+        Some(bday) => parse_bday(bday.trim())
+            .ok_or_else(|| format!("cannot read birthday '{}'", bday.trim()))?,
+        None => BirthDate::Unknown,
+    };
+
+    let id = get("UID").map(unescape).unwrap_or_default();
+    let all = |name: &'static str| {
+        properties
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    let mut favorite_sports: Vec<Sport> = all(SPORT_PROPERTY)
+        .map(|sport| Sport::from_string(&unescape(sport)))
+        .collect();
+    if favorite_sports.is_empty() {
+        // Contacts without a sport are imported without one
+        favorite_sports.extend(all("NOTE").find_map(|v| {
+            let note = unescape(v);
+            note.lines().find_map(|l| {
+                l.trim()
+                    .strip_prefix(SPORT_NOTE_PREFIX)
+                    .map(Sport::from_string)
+            })
+        }));
+    }
+
+    let mut person = Person::with_id(
+        id.trim().to_string(),
+        first_name.trim().to_string(),
+        last_name.trim().to_string(),
+        date_of_birth,
+        favorite_sports,
+    );
+    person.emails = all("EMAIL")
+        .map(|v| unescape(v).trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    // Numbers that cannot be normalized are kept as written
+    person.phones = all("TEL")
+        .map(|v| {
+            let number = unescape(v);
+            let number = number.trim();
+            let number = number.strip_prefix("tel:").unwrap_or(number);
+            parse_phone(number).unwrap_or_else(|_| number.to_string())
+        })
+        .filter(|v| !v.is_empty())
+        .collect();
+    person.address = get("ADR")
+        .map(|adr| {
+            let parts: Vec<String> = split_unescaped(adr, ';')
+                .iter()
+                .map(|part| unescape(part).trim().to_string())
+                .filter(|part| !part.is_empty())
+                .collect();
+            parts.join(", ")
+        })
+        .unwrap_or_default();
+    // The sport line written by other tools is not part of the notes
+    person.notes = all("NOTE")
+        .map(|v| {
+            let note = unescape(v);
+            let lines: Vec<&str> = note
+                .lines()
+                .filter(|l| !l.trim().starts_with(SPORT_NOTE_PREFIX))
+                .collect();
+            lines.join("\n").trim().to_string()
+        })
+        .filter(|note| !note.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Address books may use any case for their categories
+    for categories in all("CATEGORIES") {
+        for tag in split_unescaped(categories, ',') {
+            let tag = unescape(&tag).trim().to_lowercase();
+            if !tag.is_empty() && !person.has_tag(&tag) {
+                person.tags.push(tag);
+            }
+        }
+    }
+    Ok(person)
+}
+
+// Accepts YYYY-MM-DD and YYYYMMDD, optionally followed by a time as some 3.0 exporters write,
+// and the partial forms YYYY and --MMDD (or --MM-DD)
+fn parse_bday(value: &str) -> Option<BirthDate> {
+    let date = value.split('T').next().unwrap_or(value);
+    if let Some(month_day) = date.strip_prefix("--") {
+        let month_day = month_day.replace('-', "");
+        let (month, day) = month_day.split_at_checked(2)?;
+        return BirthDate::month_day(month.parse().ok()?, day.parse().ok()?);
+    }
+    let full = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y%m%d"))
+        .ok();
+    match full {
+        // Some address books write birthdays without a year as 1604
+        Some(date) if date.year() == 1604 => BirthDate::month_day(date.month(), date.day()),
+        Some(date) => Some(BirthDate::Full(date)),
+        None => BirthDate::parse(date).filter(|d| matches!(d, BirthDate::Year(_))),
+    }
+}
+
+// Joins folded lines (continuations start with a space or tab), keeping the line number
+// each logical line starts on
+fn unfold(content: &str) -> Vec<(u64, String)> {
+    let mut lines: Vec<(u64, String)> = Vec::new();
+    for (i, raw) in content.lines().enumerate() {
+        let raw = raw.trim_end_matches('\r');
+        if raw.starts_with(' ') || raw.starts_with('\t') {
+            if let Some((_, last)) = lines.last_mut() {
+                last.push_str(&raw[1..]);
+                continue;
+            }
+        }
+        lines.push((i as u64 + 1, raw.to_string()));
+    }
+    lines
+}
+
+// Splits `group.NAME;PARAM=x:value` into the upper-case name and the value. Parameters
+// (TYPE, VALUE, ...) do not affect any of the fields that are mapped.
+fn split_property(line: &str) -> Option<(String, String)> {
+    let (head, value) = line.split_once(':')?;
+    let name = head.split(';').next().unwrap_or(head);
+    let name = name.rsplit('.').next().unwrap_or(name).trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, value.to_string()))
+}
+
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            let last = parts.last_mut().unwrap();
+            last.push('\\');
+            last.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts
+}
+
+/// Escapes a text value for a vCard or iCalendar content line.
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Folds a vCard or iCalendar content line at 75 octets without splitting UTF-8
+/// characters, and terminates it with CRLF.
+pub fn fold(line: &str) -> String {
+    let mut result = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            width = 1;
+        }
+        result.push(c);
+        width += c.len_utf8();
+    }
+    result.push_str("\r\n");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(first: &str, last: &str, date_of_birth: BirthDate) -> Person {
+        Person::with_id("7".to_string(), first.to_string(), last.to_string(), date_of_birth, vec![Sport::Tennis])
+    }
+
+    fn round_trip(person: &Person, version: VcardVersion) -> Person {
+        let (mut people, problems) = parse_vcards(&format_vcard(person, version));
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(people.len(), 1);
+        people.remove(0)
+    }
+
+    fn parse_one(content: &str) -> Person {
+        let (mut people, problems) = parse_vcards(&content.replace('\n', "\r\n"));
+        assert!(problems.is_empty(), "{:?}", problems);
+        people.remove(0)
+    }
+
+    #[test]
+    fn long_lines_fold_between_characters() {
+        let mut ann = person("Zoë", "Müller-Łukasiewicz", BirthDate::Unknown);
+        ann.notes = "Ünïcödé ".repeat(20).trim().to_string();
+        let vcard = format_vcard(&ann, VcardVersion::V4);
+        for line in vcard.split("\r\n") {
+            assert!(line.len() <= 75, "{}", line);
+        }
+        assert!(vcard.contains("\r\n "));
+        assert_eq!(round_trip(&ann, VcardVersion::V4), ann);
+        assert_eq!(round_trip(&ann, VcardVersion::V3), ann);
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let mut ann = person("Ann; Marie", "O\\Brien, Jr.", BirthDate::Unknown);
+        ann.notes = "Allergic: nuts, eggs; bring snacks\nCall first".to_string();
+        ann.tags = vec!["u12, blue".to_string(), "team;a".to_string()];
+        ann.address = "1 Main St, Springfield".to_string();
+        let vcard = format_vcard(&ann, VcardVersion::V3);
+        assert!(vcard.contains("N:O\\\\Brien\\, Jr.;Ann\\; Marie;;;"), "{}", vcard);
+        assert!(vcard.contains("CATEGORIES:u12\\, blue,team\\;a"), "{}", vcard);
+        assert_eq!(round_trip(&ann, VcardVersion::V3), ann);
+    }
+
+    #[test]
+    fn phone_numbers_kept_as_written_round_trip() {
+        let mut ann = person("Ann", "Miller", BirthDate::Unknown);
+        ann.phones = vec!["+15551234567".to_string(), "ext. 12; ask, for Ann".to_string()];
+        assert!(format_vcard(&ann, VcardVersion::V3).contains("TEL:ext. 12\\; ask\\, for Ann\r\n"));
+        assert_eq!(round_trip(&ann, VcardVersion::V3), ann);
+    }
+
+    #[test]
+    fn birthdays_in_every_form() {
+        let full = BirthDate::Full(NaiveDate::from_ymd_opt(1990, 5, 6).unwrap());
+        for version in [VcardVersion::V3, VcardVersion::V4] {
+            assert_eq!(round_trip(&person("Ann", "Miller", full), version).date_of_birth, full);
+        }
+        for partial in [BirthDate::Year(1990), BirthDate::MonthDay { month: 2, day: 29 }] {
+            let ann = person("Ann", "Miller", partial);
+            assert_eq!(round_trip(&ann, VcardVersion::V4).date_of_birth, partial);
+            assert_eq!(round_trip(&ann, VcardVersion::V3).date_of_birth, BirthDate::Unknown);
+        }
+
+        let bday = |value: &str| {
+            parse_one(&format!("BEGIN:VCARD\nVERSION:3.0\nFN:Ann Miller\nBDAY:{}\nEND:VCARD\n", value)).date_of_birth
+        };
+        assert_eq!(bday("1990-05-06T00:00:00Z"), full);
+        assert_eq!(bday("19900506"), full);
+        assert_eq!(bday("1604-05-06"), BirthDate::MonthDay { month: 5, day: 6 });
+        assert_eq!(bday("--0506"), BirthDate::MonthDay { month: 5, day: 6 });
+        assert_eq!(bday("1990"), BirthDate::Year(1990));
+    }
+
+    #[test]
+    fn problems_are_reported_at_the_begin_line() {
+        let content = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ann Miller\r\nEND:VCARD\r\n\
+                       BEGIN:VCARD\r\nVERSION:4.0\r\nEMAIL:nobody@example.com\r\nEND:VCARD\r\n\
+                       BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Bob Smith\r\n";
+        let (people, problems) = parse_vcards(content);
+        assert_eq!(people.len(), 1);
+        assert_eq!((people[0].first_name.as_str(), people[0].last_name.as_str()), ("Ann", "Miller"));
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, Some(5));
+        assert_eq!(problems[0].reason, "contact has no name (N or FN)");
+        assert_eq!(problems[1].line, Some(9));
+        assert_eq!(problems[1].reason, "contact is missing END:VCARD");
+    }
+}